@@ -0,0 +1,1021 @@
+pub const APU_START: u16 = 0xFF10;
+pub const APU_END: u16 = 0xFF3F;
+
+const NR10_ADDRESS: u16 = 0xFF10;
+const NR11_ADDRESS: u16 = 0xFF11;
+const NR12_ADDRESS: u16 = 0xFF12;
+const NR13_ADDRESS: u16 = 0xFF13;
+const NR14_ADDRESS: u16 = 0xFF14;
+const NR21_ADDRESS: u16 = 0xFF16;
+const NR22_ADDRESS: u16 = 0xFF17;
+const NR23_ADDRESS: u16 = 0xFF18;
+const NR24_ADDRESS: u16 = 0xFF19;
+const NR30_ADDRESS: u16 = 0xFF1A;
+const NR31_ADDRESS: u16 = 0xFF1B;
+const NR32_ADDRESS: u16 = 0xFF1C;
+const NR33_ADDRESS: u16 = 0xFF1D;
+const NR34_ADDRESS: u16 = 0xFF1E;
+const NR41_ADDRESS: u16 = 0xFF20;
+const NR42_ADDRESS: u16 = 0xFF21;
+const NR43_ADDRESS: u16 = 0xFF22;
+const NR44_ADDRESS: u16 = 0xFF23;
+const NR50_ADDRESS: u16 = 0xFF24;
+const NR51_ADDRESS: u16 = 0xFF25;
+const NR52_ADDRESS: u16 = 0xFF26;
+const WAVE_RAM_START: u16 = 0xFF30;
+const WAVE_RAM_END: u16 = 0xFF3F;
+
+// Bits that always read back as 1, from NR10 to NR52 (https://gbdev.io/pandocs/Audio_Registers.html)
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // (unused) NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // (unused) NR41-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+const CPU_CLOCK_HZ: u32 = 4_194_304;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+// 512 Hz
+const T_CYCLES_PER_FRAME_SEQUENCER_STEP: u32 = 8192;
+// Oldest half gets dropped past this, frontends are expected to drain the buffer every frame
+const MAX_BUFFERED_SAMPLES: usize = DEFAULT_SAMPLE_RATE as usize * 2;
+
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+const TRIGGER: u8 = 0b1000_0000;
+const LENGTH_ENABLE: u8 = 0b0100_0000;
+
+// Shared by both squares and the noise channel. NRx2: initial volume, direction, period.
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0b0000_1000 > 0;
+        self.period = value & 0b0000_0111;
+    }
+
+    // The dac is only on if any of the top 5 bits are set
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume > 0 || self.increase
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+// Counts down at 256 Hz and switches the channel off when it runs out
+struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // Returns true when the channel should be switched off
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+}
+
+// Channel 1 only
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    shadow_frequency: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Self {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            shadow_frequency: 0,
+            enabled: false,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.period = (value >> 4) & 0b111;
+        self.negate = value & 0b0000_1000 > 0;
+        self.shift = value & 0b0000_0111;
+    }
+
+    // A period of 0 is treated as 8 for the timer
+    fn reload_timer(&mut self) {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    // None means the result overflowed 11 bits and the channel gets switched off
+    fn next_frequency(&self) -> Option<u16> {
+        let delta = self.shadow_frequency >> self.shift;
+        let frequency = if self.negate {
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+
+        if frequency > 2047 {
+            None
+        } else {
+            Some(frequency)
+        }
+    }
+}
+
+struct SquareChannel {
+    enabled: bool,
+    duty: u8,
+    duty_position: u8,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    fn new(has_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_position: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: if has_sweep { Some(Sweep::new()) } else { None },
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.duty_position = (self.duty_position + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let high = DUTY_PATTERNS[self.duty as usize] & (0b1000_0000 >> self.duty_position) > 0;
+        if high { self.envelope.volume } else { 0 }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period > 0 || sweep.shift > 0;
+
+            // The overflow check happens straight away on trigger
+            if sweep.shift > 0 && sweep.next_frequency().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        match sweep.next_frequency() {
+            Some(frequency) if sweep.shift > 0 => {
+                sweep.shadow_frequency = frequency;
+                self.frequency = frequency;
+
+                // And once more with the new frequency, only to check for overflow
+                if sweep.next_frequency().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => {}
+            None => self.enabled = false,
+        }
+    }
+}
+
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    // Right shift applied to the 4 bit samples, 4 mutes it
+    volume_shift: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    length: LengthCounter,
+    wave_ram: [u8; 16],
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume_shift: 4,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0; 16],
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0b1_1111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // 32 samples, high nibble first
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let byte = self.wave_ram[self.position as usize / 2];
+        let sample = if self.position.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+        sample >> self.volume_shift
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+}
+
+struct NoiseChannel {
+    enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    // 15 bit LFSR, the 7 bit mode also feeds the result back into bit 6
+    fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 0x01 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+}
+
+// Hardware has a capacitor on each output that removes the dc offset of the dacs
+struct HighPassFilter {
+    capacitor: f32,
+    charge_factor: f32,
+}
+
+impl HighPassFilter {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            capacitor: 0.0,
+            charge_factor: 0.999958_f32.powf(CPU_CLOCK_HZ as f32 / sample_rate as f32),
+        }
+    }
+
+    fn apply(&mut self, input: f32) -> f32 {
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.charge_factor;
+        output
+    }
+}
+
+// NR10-NR52 and wave ram (https://gbdev.io/pandocs/Audio.html)
+pub struct Apu {
+    powered: bool,
+    // Last written values, for reading back through READ_MASKS
+    registers: [u8; 0x17],
+
+    square_1: SquareChannel,
+    square_2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+
+    frame_sequencer_step: u8,
+    frame_sequencer_cycles: u32,
+
+    sample_rate: u32,
+    // Counts up by the sample rate every t-cycle, a sample is due each time it passes the cpu clock
+    sample_cycles: u32,
+    // Interleaved left/right
+    samples: Vec<f32>,
+    high_pass_left: HighPassFilter,
+    high_pass_right: HighPassFilter,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            powered: false,
+            registers: [0; 0x17],
+
+            square_1: SquareChannel::new(true),
+            square_2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+
+            frame_sequencer_step: 0,
+            frame_sequencer_cycles: 0,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_cycles: 0,
+            samples: Vec::new(),
+            high_pass_left: HighPassFilter::new(DEFAULT_SAMPLE_RATE),
+            high_pass_right: HighPassFilter::new(DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        assert!(sample_rate > 0, "Sample rate has to be above 0");
+
+        self.sample_rate = sample_rate;
+        self.sample_cycles = 0;
+        self.high_pass_left = HighPassFilter::new(sample_rate);
+        self.high_pass_right = HighPassFilter::new(sample_rate);
+    }
+
+    // Channel 1 the way the boot rom leaves it: still on, with the chime faded out to volume 0
+    pub fn skip_boot_chime(&mut self) {
+        self.square_1.enabled = self.powered && self.square_1.envelope.dac_enabled();
+        self.square_1.envelope.volume = 0;
+    }
+
+    pub fn buffered_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    // Everything generated since the last call, interleaved left/right in -1.0..=1.0
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn step(&mut self, t_cycles: u32) {
+        for _ in 0..t_cycles {
+            if self.powered {
+                self.tick();
+            }
+
+            self.sample_cycles += self.sample_rate;
+            if self.sample_cycles >= CPU_CLOCK_HZ {
+                self.sample_cycles -= CPU_CLOCK_HZ;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        self.frame_sequencer_cycles += 1;
+        if self.frame_sequencer_cycles == T_CYCLES_PER_FRAME_SEQUENCER_STEP {
+            self.frame_sequencer_cycles = 0;
+            self.clock_frame_sequencer();
+        }
+
+        self.square_1.tick();
+        self.square_2.tick();
+        self.wave.tick();
+        self.noise.tick();
+    }
+
+    // Length on even steps, sweep on 2 and 6, envelope on 7
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+
+        if step.is_multiple_of(2) {
+            if self.square_1.length.clock() {
+                self.square_1.enabled = false;
+            }
+            if self.square_2.length.clock() {
+                self.square_2.enabled = false;
+            }
+            if self.wave.length.clock() {
+                self.wave.enabled = false;
+            }
+            if self.noise.length.clock() {
+                self.noise.enabled = false;
+            }
+        }
+
+        if step == 2 || step == 6 {
+            self.square_1.clock_sweep();
+        }
+
+        if step == 7 {
+            self.square_1.envelope.clock();
+            self.square_2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.frame_sequencer_step = (step + 1) & 0b111;
+    }
+
+    fn push_sample(&mut self) {
+        // Each dac turns 0-15 into -1.0..=1.0, a dac that's off contributes nothing
+        let dac = |enabled: bool, digital: u8| {
+            if enabled {
+                digital as f32 / 7.5 - 1.0
+            } else {
+                0.0
+            }
+        };
+        let channels = [
+            dac(self.square_1.envelope.dac_enabled(), self.square_1.output()),
+            dac(self.square_2.envelope.dac_enabled(), self.square_2.output()),
+            dac(self.wave.dac_enabled, self.wave.output()),
+            dac(self.noise.envelope.dac_enabled(), self.noise.output()),
+        ];
+
+        let nr50 = self.registers[(NR50_ADDRESS - APU_START) as usize];
+        let nr51 = self.registers[(NR51_ADDRESS - APU_START) as usize];
+
+        // NR51 picks which channels go to which side, NR50 sets each side's volume 1-8
+        let mix = |panning: u8, volume: u8| {
+            let sum: f32 = channels
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| panning & (1 << i) > 0)
+                .map(|(_, amplitude)| amplitude)
+                .sum();
+            sum / 4.0 * (volume + 1) as f32 / 8.0
+        };
+
+        let (left, right) = if self.powered {
+            (
+                mix(nr51 >> 4, (nr50 >> 4) & 0b111),
+                mix(nr51 & 0x0F, nr50 & 0b111),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        if self.samples.len() >= MAX_BUFFERED_SAMPLES {
+            self.samples.drain(..MAX_BUFFERED_SAMPLES / 2);
+        }
+        self.samples.push(self.high_pass_left.apply(left));
+        self.samples.push(self.high_pass_right.apply(right));
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            NR52_ADDRESS => {
+                let mut value = 0b0111_0000;
+                if self.powered {
+                    value |= 0b1000_0000;
+                }
+                if self.square_1.enabled {
+                    value |= 0b0000_0001;
+                }
+                if self.square_2.enabled {
+                    value |= 0b0000_0010;
+                }
+                if self.wave.enabled {
+                    value |= 0b0000_0100;
+                }
+                if self.noise.enabled {
+                    value |= 0b0000_1000;
+                }
+                value
+            }
+            APU_START..NR52_ADDRESS => {
+                let index = (address - APU_START) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.wave.wave_ram[(address - WAVE_RAM_START) as usize]
+            }
+            // 0xFF27-0xFF2F
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        // Wave ram is still reachable with the apu off, everything else but NR52 is ignored
+        if let WAVE_RAM_START..=WAVE_RAM_END = address {
+            self.wave.wave_ram[(address - WAVE_RAM_START) as usize] = value;
+            return;
+        }
+        if address == NR52_ADDRESS {
+            self.write_power(value);
+            return;
+        }
+        if !self.powered || !(APU_START..NR52_ADDRESS).contains(&address) {
+            return;
+        }
+
+        self.registers[(address - APU_START) as usize] = value;
+
+        match address {
+            NR10_ADDRESS => {
+                if let Some(sweep) = &mut self.square_1.sweep {
+                    sweep.write(value);
+                }
+            }
+            NR11_ADDRESS => {
+                self.square_1.duty = value >> 6;
+                self.square_1.length.load(value & 0b0011_1111);
+            }
+            NR12_ADDRESS => {
+                self.square_1.envelope.write(value);
+                if !self.square_1.envelope.dac_enabled() {
+                    self.square_1.enabled = false;
+                }
+            }
+            NR13_ADDRESS => {
+                self.square_1.frequency = (self.square_1.frequency & 0x0700) | value as u16;
+            }
+            NR14_ADDRESS => {
+                self.square_1.frequency =
+                    (self.square_1.frequency & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.square_1.length.enabled = value & LENGTH_ENABLE > 0;
+                if value & TRIGGER > 0 {
+                    self.square_1.trigger();
+                }
+            }
+            NR21_ADDRESS => {
+                self.square_2.duty = value >> 6;
+                self.square_2.length.load(value & 0b0011_1111);
+            }
+            NR22_ADDRESS => {
+                self.square_2.envelope.write(value);
+                if !self.square_2.envelope.dac_enabled() {
+                    self.square_2.enabled = false;
+                }
+            }
+            NR23_ADDRESS => {
+                self.square_2.frequency = (self.square_2.frequency & 0x0700) | value as u16;
+            }
+            NR24_ADDRESS => {
+                self.square_2.frequency =
+                    (self.square_2.frequency & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.square_2.length.enabled = value & LENGTH_ENABLE > 0;
+                if value & TRIGGER > 0 {
+                    self.square_2.trigger();
+                }
+            }
+            NR30_ADDRESS => {
+                self.wave.dac_enabled = value & 0b1000_0000 > 0;
+                if !self.wave.dac_enabled {
+                    self.wave.enabled = false;
+                }
+            }
+            NR31_ADDRESS => self.wave.length.load(value),
+            NR32_ADDRESS => {
+                self.wave.volume_shift = match (value >> 5) & 0b11 {
+                    0 => 4,
+                    1 => 0,
+                    2 => 1,
+                    _ => 2,
+                };
+            }
+            NR33_ADDRESS => {
+                self.wave.frequency = (self.wave.frequency & 0x0700) | value as u16;
+            }
+            NR34_ADDRESS => {
+                self.wave.frequency =
+                    (self.wave.frequency & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.wave.length.enabled = value & LENGTH_ENABLE > 0;
+                if value & TRIGGER > 0 {
+                    self.wave.trigger();
+                }
+            }
+            NR41_ADDRESS => self.noise.length.load(value & 0b0011_1111),
+            NR42_ADDRESS => {
+                self.noise.envelope.write(value);
+                if !self.noise.envelope.dac_enabled() {
+                    self.noise.enabled = false;
+                }
+            }
+            NR43_ADDRESS => {
+                self.noise.clock_shift = value >> 4;
+                self.noise.short_mode = value & 0b0000_1000 > 0;
+                self.noise.divisor_code = value & 0b0000_0111;
+            }
+            NR44_ADDRESS => {
+                self.noise.length.enabled = value & LENGTH_ENABLE > 0;
+                if value & TRIGGER > 0 {
+                    self.noise.trigger();
+                }
+            }
+            // NR50/NR51 are only read back when mixing
+            _ => {}
+        }
+    }
+
+    // Powering off clears every register and stops all the channels, wave ram survives
+    fn write_power(&mut self, value: u8) {
+        let powered = value & 0b1000_0000 > 0;
+
+        if self.powered && !powered {
+            for address in APU_START..NR52_ADDRESS {
+                self.write_byte(address, 0x00);
+            }
+            let wave_ram = self.wave.wave_ram;
+            self.square_1 = SquareChannel::new(true);
+            self.square_2 = SquareChannel::new(false);
+            self.wave = WaveChannel::new();
+            self.wave.wave_ram = wave_ram;
+            self.noise = NoiseChannel::new();
+        } else if !self.powered && powered {
+            self.frame_sequencer_step = 0;
+            self.frame_sequencer_cycles = 0;
+        }
+
+        self.powered = powered;
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powered_apu() -> Apu {
+        let mut apu = Apu::new();
+        apu.write_byte(NR52_ADDRESS, 0x80);
+        apu.write_byte(NR50_ADDRESS, 0x77);
+        apu.write_byte(NR51_ADDRESS, 0xFF);
+        apu
+    }
+
+    fn channel_status(apu: &Apu) -> u8 {
+        apu.read_byte(NR52_ADDRESS) & 0x0F
+    }
+
+    #[test]
+    fn registers_read_back_with_masks() {
+        let mut apu = powered_apu();
+        apu.write_byte(NR11_ADDRESS, 0b1010_1010);
+        apu.write_byte(NR13_ADDRESS, 0x12);
+        apu.write_byte(NR30_ADDRESS, 0x00);
+
+        assert_eq!(apu.read_byte(NR10_ADDRESS), 0x80);
+        assert_eq!(apu.read_byte(NR11_ADDRESS), 0b1011_1111);
+        assert_eq!(apu.read_byte(NR13_ADDRESS), 0xFF);
+        assert_eq!(apu.read_byte(NR30_ADDRESS), 0x7F);
+        assert_eq!(apu.read_byte(NR50_ADDRESS), 0x77);
+        assert_eq!(apu.read_byte(0xFF27), 0xFF);
+        assert_eq!(apu.read_byte(NR52_ADDRESS), 0xF0);
+    }
+
+    #[test]
+    fn power_off_clears_registers_and_ignores_writes() {
+        let mut apu = powered_apu();
+        apu.write_byte(WAVE_RAM_START, 0xAB);
+        apu.write_byte(NR12_ADDRESS, 0xF0);
+        apu.write_byte(NR14_ADDRESS, 0x80);
+        assert_eq!(channel_status(&apu), 0b0001);
+
+        apu.write_byte(NR52_ADDRESS, 0x00);
+        assert_eq!(apu.read_byte(NR52_ADDRESS), 0x70);
+        assert_eq!(apu.read_byte(NR12_ADDRESS), 0x00);
+        assert_eq!(apu.read_byte(NR50_ADDRESS), 0x00);
+
+        apu.write_byte(NR12_ADDRESS, 0xF0);
+        assert_eq!(apu.read_byte(NR12_ADDRESS), 0x00);
+
+        // Wave ram isn't touched either way
+        apu.write_byte(WAVE_RAM_START + 1, 0xCD);
+        assert_eq!(apu.read_byte(WAVE_RAM_START), 0xAB);
+        assert_eq!(apu.read_byte(WAVE_RAM_START + 1), 0xCD);
+    }
+
+    #[test]
+    fn trigger_needs_dac() {
+        let mut apu = powered_apu();
+        apu.write_byte(NR22_ADDRESS, 0x00);
+        apu.write_byte(NR24_ADDRESS, 0x80);
+        assert_eq!(channel_status(&apu), 0b0000);
+
+        apu.write_byte(NR22_ADDRESS, 0x08);
+        apu.write_byte(NR24_ADDRESS, 0x80);
+        assert_eq!(channel_status(&apu), 0b0010);
+
+        // Turning the dac off kills the channel
+        apu.write_byte(NR22_ADDRESS, 0x00);
+        assert_eq!(channel_status(&apu), 0b0000);
+    }
+
+    #[test]
+    fn length_counter_switches_channel_off() {
+        let mut apu = powered_apu();
+        apu.write_byte(NR42_ADDRESS, 0xF0);
+        // 64 - 60 = 4 length clocks, 256 Hz
+        apu.write_byte(NR41_ADDRESS, 60);
+        apu.write_byte(NR44_ADDRESS, 0b1100_0000);
+        assert_eq!(channel_status(&apu), 0b1000);
+
+        // Steps 0, 2 and 4
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP * 6);
+        assert_eq!(channel_status(&apu), 0b1000);
+
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP);
+        assert_eq!(channel_status(&apu), 0b0000);
+    }
+
+    #[test]
+    fn length_disabled_keeps_playing() {
+        let mut apu = powered_apu();
+        apu.write_byte(NR30_ADDRESS, 0x80);
+        apu.write_byte(NR31_ADDRESS, 0xFF);
+        apu.write_byte(NR34_ADDRESS, 0x80);
+
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP * 16);
+        assert_eq!(channel_status(&apu), 0b0100);
+    }
+
+    #[test]
+    fn envelope_steps_volume() {
+        let mut apu = powered_apu();
+        // Volume 2, decreasing every envelope clock (64 Hz)
+        apu.write_byte(NR12_ADDRESS, 0x21);
+        apu.write_byte(NR14_ADDRESS, 0x80);
+        assert_eq!(apu.square_1.envelope.volume, 2);
+
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP * 8);
+        assert_eq!(apu.square_1.envelope.volume, 1);
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP * 16);
+        assert_eq!(apu.square_1.envelope.volume, 0);
+
+        // Still on, just silent
+        assert_eq!(channel_status(&apu), 0b0001);
+    }
+
+    #[test]
+    fn sweep_raises_frequency_then_overflows() {
+        let mut apu = powered_apu();
+        // Period 1, increasing, shift 1
+        apu.write_byte(NR10_ADDRESS, 0b0001_0001);
+        apu.write_byte(NR12_ADDRESS, 0xF0);
+        apu.write_byte(NR13_ADDRESS, 0x00);
+        apu.write_byte(NR14_ADDRESS, 0x84);
+        assert_eq!(apu.square_1.frequency, 0x400);
+
+        // First sweep clock is on step 2: 0x400 + 0x200, and 0x600 + 0x300 overflows
+        apu.step(T_CYCLES_PER_FRAME_SEQUENCER_STEP * 3);
+        assert_eq!(apu.square_1.frequency, 0x600);
+        assert_eq!(channel_status(&apu), 0b0000);
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger() {
+        let mut apu = powered_apu();
+        apu.write_byte(NR10_ADDRESS, 0b0001_0001);
+        apu.write_byte(NR12_ADDRESS, 0xF0);
+        apu.write_byte(NR13_ADDRESS, 0xFF);
+        apu.write_byte(NR14_ADDRESS, 0x87);
+
+        assert_eq!(channel_status(&apu), 0b0000);
+    }
+
+    #[test]
+    fn square_duty_cycle() {
+        let mut channel = SquareChannel::new(false);
+        channel.envelope.write(0xF0);
+        channel.duty = 2;
+        channel.frequency = 2047;
+        channel.trigger();
+
+        // 50%: 4 of every 8 steps high, 4 t-cycles per step at this frequency
+        let mut high = 0;
+        for _ in 0..8 {
+            channel.tick();
+            channel.tick();
+            channel.tick();
+            channel.tick();
+            if channel.output() == 15 {
+                high += 1;
+            }
+        }
+        assert_eq!(high, 4);
+    }
+
+    #[test]
+    fn wave_plays_ram_with_volume_shift() {
+        let mut apu = powered_apu();
+        apu.write_byte(WAVE_RAM_START, 0xF8);
+        apu.write_byte(NR30_ADDRESS, 0x80);
+        // 50%
+        apu.write_byte(NR32_ADDRESS, 0b0100_0000);
+        apu.write_byte(NR33_ADDRESS, 0xFF);
+        apu.write_byte(NR34_ADDRESS, 0x87);
+
+        assert_eq!(apu.wave.output(), 0x07);
+        apu.wave.tick();
+        apu.wave.tick();
+        assert_eq!(apu.wave.output(), 0x04);
+    }
+
+    #[test]
+    fn noise_lfsr_modes() {
+        let mut channel = NoiseChannel::new();
+        channel.envelope.write(0xF0);
+        channel.trigger();
+
+        // 15 bit mode repeats every 32767 clocks, 7 bit mode every 127
+        let start = channel.lfsr;
+        let mut period = 0;
+        loop {
+            channel.timer = 1;
+            channel.tick();
+            period += 1;
+            if channel.lfsr == start {
+                break;
+            }
+        }
+        assert_eq!(period, 32767);
+
+        channel.short_mode = true;
+        channel.trigger();
+        for _ in 0..200 {
+            channel.timer = 1;
+            channel.tick();
+        }
+        let start = channel.lfsr;
+        let mut period = 0;
+        loop {
+            channel.timer = 1;
+            channel.tick();
+            period += 1;
+            if channel.lfsr == start {
+                break;
+            }
+        }
+        assert_eq!(period, 127);
+    }
+
+    #[test]
+    fn sample_rate_and_panning() {
+        let mut apu = powered_apu();
+        apu.set_sample_rate(32_768);
+        // Square 2 loud on the left only
+        apu.write_byte(NR51_ADDRESS, 0b0010_0000);
+        apu.write_byte(NR21_ADDRESS, 0b1000_0000);
+        apu.write_byte(NR22_ADDRESS, 0xF0);
+        apu.write_byte(NR23_ADDRESS, 0x00);
+        apu.write_byte(NR24_ADDRESS, 0x87);
+
+        apu.step(CPU_CLOCK_HZ / 16);
+        let samples = apu.take_samples();
+
+        assert_eq!(samples.len(), 2048 * 2);
+        assert!(samples.iter().step_by(2).any(|left| left.abs() > 0.1));
+        assert!(samples.iter().skip(1).step_by(2).all(|right| *right == 0.0));
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(apu.take_samples().is_empty());
+    }
+}
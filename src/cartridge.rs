@@ -1,38 +1,743 @@
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::mbc::{
+    Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE, RTC_SAVE_SIZE, RomOnly, builtin_ram_size, create_mbc,
+};
+
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const MANUFACTURER_CODE_START: usize = 0x013F;
+const MANUFACTURER_CODE_END: usize = 0x0142;
+const CGB_FLAG_ADDRESS: usize = 0x0143;
+const SGB_FLAG_ADDRESS: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const ROM_SIZE_ADDRESS: usize = 0x0148;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+const DESTINATION_CODE_ADDRESS: usize = 0x014A;
+const VERSION_ADDRESS: usize = 0x014C;
+const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+const GLOBAL_CHECKSUM_HIGH_ADDRESS: usize = 0x014E;
+const GLOBAL_CHECKSUM_LOW_ADDRESS: usize = 0x014F;
+const HEADER_END: usize = 0x014F;
+
+const CARTRIDGE_ROM_START: u16 = 0x0000;
+const CARTRIDGE_ROM_END: u16 = 0x7FFF;
+const CARTRIDGE_RAM_START: u16 = 0xA000;
+const CARTRIDGE_RAM_END: u16 = 0xBFFF;
+
+// Dirty battery ram gets written out about once per emulated second
+const SAVE_FLUSH_INTERVAL_M_CYCLES: u32 = 1_048_576;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    // Not even enough bytes to hold the header at 0x0100-0x014F
+    TooSmall(usize),
+    HeaderChecksumMismatch { expected: u8, calculated: u8 },
+    UnsupportedRomSizeCode(u8),
+    RomSizeMismatch { declared: usize, actual: usize },
+    UnsupportedCartridgeType(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "could not read rom file: {}", error),
+            CartridgeError::TooSmall(length) => {
+                write!(f, "rom is {} bytes, too small to hold a header", length)
+            }
+            CartridgeError::HeaderChecksumMismatch {
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "header checksum mismatch: header says {:#04X}, calculated {:#04X}",
+                expected, calculated
+            ),
+            CartridgeError::UnsupportedRomSizeCode(code) => {
+                write!(f, "unsupported rom size code {:#04X}", code)
+            }
+            CartridgeError::RomSizeMismatch { declared, actual } => write!(
+                f,
+                "rom size mismatch: header declares {} bytes, file has {} bytes",
+                declared, actual
+            ),
+            CartridgeError::UnsupportedCartridgeType(cartridge_type) => {
+                write!(f, "unsupported cartridge type {:#04X}", cartridge_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(error: io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CgbSupport {
+    DmgOnly,
+    CgbEnhanced, // 0x80, works on both
+    CgbOnly,     // 0xC0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+// Everything in 0x0134-0x014F, see https://gbdev.io/pandocs/The_Cartridge_Header.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    // Only present on later carts, empty otherwise
+    pub manufacturer_code: String,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() <= HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        let cgb_flag = rom[CGB_FLAG_ADDRESS];
+
+        // CGB era carts shrank the title to make room for the manufacturer code and cgb flag
+        let manufacturer_bytes = &rom[MANUFACTURER_CODE_START..=MANUFACTURER_CODE_END];
+        let has_manufacturer_code = cgb_flag & 0x80 > 0
+            && manufacturer_bytes
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+
+        let title_end = if has_manufacturer_code {
+            MANUFACTURER_CODE_START - 1
+        } else if cgb_flag & 0x80 > 0 {
+            CGB_FLAG_ADDRESS - 1
+        } else {
+            TITLE_END
+        };
+
+        let manufacturer_code = if has_manufacturer_code {
+            bytes_to_ascii(manufacturer_bytes)
+        } else {
+            String::new()
+        };
+
+        Ok(Self {
+            title: bytes_to_ascii(&rom[TITLE_START..=title_end]),
+            manufacturer_code,
+            cgb_flag,
+            sgb_flag: rom[SGB_FLAG_ADDRESS],
+            cartridge_type: rom[CARTRIDGE_TYPE_ADDRESS],
+            rom_size_code: rom[ROM_SIZE_ADDRESS],
+            ram_size_code: rom[RAM_SIZE_ADDRESS],
+            destination_code: rom[DESTINATION_CODE_ADDRESS],
+            version: rom[VERSION_ADDRESS],
+            header_checksum: rom[HEADER_CHECKSUM_ADDRESS],
+            global_checksum: u16::from_be_bytes([
+                rom[GLOBAL_CHECKSUM_HIGH_ADDRESS],
+                rom[GLOBAL_CHECKSUM_LOW_ADDRESS],
+            ]),
+        })
+    }
+
+    // Reads only the header, handy for listing roms without loading (or validating) them
+    pub fn read_from_file(file_path: &str) -> Result<Self, CartridgeError> {
+        let rom = fs::read(file_path)?;
+        Self::parse(&rom)
+    }
+
+    pub fn cgb_support(&self) -> CgbSupport {
+        match self.cgb_flag {
+            0xC0 => CgbSupport::CgbOnly,
+            0x80 => CgbSupport::CgbEnhanced,
+            _ => CgbSupport::DmgOnly,
+        }
+    }
+
+    pub fn sgb_support(&self) -> bool {
+        self.sgb_flag == 0x03
+    }
+
+    pub fn destination(&self) -> Destination {
+        match self.destination_code {
+            0x00 => Destination::Japan,
+            _ => Destination::Overseas,
+        }
+    }
+
+    // 32 KiB << code, codes past 0x08 (8 MiB) never shipped
+    pub fn rom_size_bytes(&self) -> Option<usize> {
+        match self.rom_size_code {
+            0x00..=0x08 => Some((2 * ROM_BANK_SIZE) << self.rom_size_code),
+            _ => None,
+        }
+    }
+
+    pub fn rom_bank_count(&self) -> Option<usize> {
+        self.rom_size_bytes().map(|size| size / ROM_BANK_SIZE)
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+
+    pub fn ram_size_bytes(&self) -> usize {
+        match self.ram_size_code {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            // 0x01 was never used by a licensed cart
+            _ => 0,
+        }
+    }
+
+    pub fn cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+
+    // x = x - byte - 1 over 0x0134-0x014C, the boot rom locks up if this doesn't match
+    pub fn calculate_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_START..HEADER_CHECKSUM_ADDRESS]
+            .iter()
+            .fold(0u8, |checksum, byte| {
+                checksum.wrapping_sub(*byte).wrapping_sub(1)
+            })
+    }
+
+    // Sum of every byte except the checksum itself. Real hardware never checks this one.
+    pub fn calculate_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                *index != GLOBAL_CHECKSUM_HIGH_ADDRESS && *index != GLOBAL_CHECKSUM_LOW_ADDRESS
+            })
+            .fold(0u16, |checksum, (_, byte)| {
+                checksum.wrapping_add(*byte as u16)
+            })
+    }
+}
+
+// Header strings are padded with 0x00 (sometimes 0x80 flag bytes sneak in on old carts)
+fn bytes_to_ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0x00)
+        .filter(|byte| byte.is_ascii_graphic() || **byte == b' ')
+        .map(|byte| *byte as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
 
 #[derive(Debug)]
 pub struct Cartridge {
-    // TODO segment into rom/ram and banks
-    pub bytes: Vec<u8>,
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    // None until a rom has been loaded
+    pub header: Option<CartridgeHeader>,
+    mbc: Box<dyn Mbc>,
+    // Where battery backed ram lives on disk, None for carts without a battery
+    save_path: Option<PathBuf>,
+    ram_dirty: bool,
+    cycles_since_flush: u32,
+    // Kept across load_rom_file, so it's in place before the save's rtc footer is read
+    rtc_sync_to_host: bool,
 }
 
 impl Cartridge {
+    // Blank 32 KiB rom with 8 KiB of ram, so there's always something to poke at without a rom
     pub fn new() -> Self {
         Self {
-            bytes: vec![0; 0xFFFF],
+            rom: vec![0; 2 * ROM_BANK_SIZE],
+            ram: vec![0; RAM_BANK_SIZE],
+            header: None,
+            mbc: Box::new(RomOnly::new()),
+            save_path: None,
+            ram_dirty: false,
+            cycles_since_flush: 0,
+            rtc_sync_to_host: false,
+        }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, CartridgeError> {
+        let header = CartridgeHeader::parse(&bytes)?;
+
+        let calculated = CartridgeHeader::calculate_header_checksum(&bytes);
+        if calculated != header.header_checksum {
+            return Err(CartridgeError::HeaderChecksumMismatch {
+                expected: header.header_checksum,
+                calculated,
+            });
+        }
+
+        let declared = header
+            .rom_size_bytes()
+            .ok_or(CartridgeError::UnsupportedRomSizeCode(header.rom_size_code))?;
+        if declared != bytes.len() {
+            return Err(CartridgeError::RomSizeMismatch {
+                declared,
+                actual: bytes.len(),
+            });
+        }
+
+        let mbc = create_mbc(header.cartridge_type).ok_or(
+            CartridgeError::UnsupportedCartridgeType(header.cartridge_type),
+        )?;
+
+        let ram_size = builtin_ram_size(header.cartridge_type).unwrap_or(header.ram_size_bytes());
+
+        Ok(Self {
+            rom: bytes,
+            ram: vec![0; ram_size],
+            header: Some(header),
+            mbc,
+            save_path: None,
+            ram_dirty: false,
+            cycles_since_flush: 0,
+            rtc_sync_to_host: false,
+        })
+    }
+
+    // Battery backed carts pick up `<rom name>.sav` from next to the rom if there is one.
+    // The cartridge being replaced isn't flushed here, see Motherboard::load_rom_file.
+    pub fn load_rom_file(&mut self, file_path: &str) -> Result<(), CartridgeError> {
+        let mut cartridge = Self::from_bytes(fs::read(file_path)?)?;
+        cartridge.set_rtc_sync_to_host(self.rtc_sync_to_host);
+
+        if cartridge
+            .header
+            .as_ref()
+            .is_some_and(|header| header.has_battery())
+        {
+            let save_path = Path::new(file_path).with_extension("sav");
+            if save_path.exists() {
+                cartridge.load_save_data(&fs::read(&save_path)?);
+            }
+            cartridge.save_path = Some(save_path);
+        }
+
+        *self = cartridge;
+        Ok(())
+    }
+
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    // None turns persistence off, e.g. for test runs that shouldn't touch saves on disk
+    pub fn set_save_path(&mut self, save_path: Option<PathBuf>) {
+        self.save_path = save_path;
+    }
+
+    // Writes to a temp file first so a crash mid-write can't eat the existing save.
+    // Has to be called before exiting, the rtc footer in particular is only written here.
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        let Some(save_path) = self.save_path.clone() else {
+            return Ok(());
+        };
+
+        let temp_path = save_path.with_extension("sav.tmp");
+        fs::write(&temp_path, self.save_data())?;
+        fs::rename(&temp_path, &save_path)?;
+
+        self.ram_dirty = false;
+        self.cycles_since_flush = 0;
+        Ok(())
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        match &self.header {
+            Some(header) => {
+                CartridgeHeader::calculate_global_checksum(&self.rom) == header.global_checksum
+            }
+            None => false,
+        }
+    }
+
+    // Only the mbc3 real time clock cares about time passing
+    pub fn step(&mut self, m_cycles: u32) {
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            rtc.step(m_cycles);
+        }
+
+        if !self.ram_dirty || self.save_path.is_none() {
+            return;
+        }
+
+        self.cycles_since_flush += m_cycles;
+        if self.cycles_since_flush >= SAVE_FLUSH_INTERVAL_M_CYCLES
+            && let Err(error) = self.flush_save()
+        {
+            eprintln!("Failed to write save file: {}", error);
+        }
+    }
+
+    // The bank a 0x0000-0x7FFF read currently lands in
+    pub fn rom_bank(&self, address: u16) -> usize {
+        let bank_count = (self.rom.len() / ROM_BANK_SIZE).max(1);
+        self.mbc.rom_bank(address) % bank_count
+    }
+
+    pub fn rumble_active(&self) -> bool {
+        self.mbc.rumble_active()
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        self.mbc.rtc().is_some()
+    }
+
+    pub fn set_rtc_sync_to_host(&mut self, sync_to_host: bool) {
+        self.rtc_sync_to_host = sync_to_host;
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            rtc.set_sync_to_host(sync_to_host);
         }
     }
 
-    // TODO validate that the rom should start at 0x0000
-    pub fn load_rom_file(&mut self, file_path: &str) {
-        self.bytes = fs::read(file_path).expect("Expected to be able to read from file. ");
-        self.print_all_bytes();
+    // Battery backed state: the external ram, followed by the rtc footer if there is a clock
+    pub fn save_data(&mut self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            data.extend_from_slice(&rtc.to_save_bytes());
+        }
+        data
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let ram_length = self.ram.len().min(data.len());
+        self.ram[..ram_length].copy_from_slice(&data[..ram_length]);
+
+        if let Some(rtc) = self.mbc.rtc_mut() {
+            let footer = &data[ram_length..];
+            if footer.len() >= RTC_SAVE_SIZE - 4 {
+                rtc.load_save_bytes(footer);
+            }
+        }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
-        self.bytes[address as usize]
+        match address {
+            CARTRIDGE_ROM_START..=CARTRIDGE_ROM_END => self.mbc.read_rom(&self.rom, address),
+            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => self.mbc.read_ram(&self.ram, address),
+            _ => panic!("Unexpected Cartridge::read_byte {}", address),
+        }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        self.bytes[address as usize] = value;
+        match address {
+            CARTRIDGE_ROM_START..=CARTRIDGE_ROM_END => self.mbc.write_register(address, value),
+            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => {
+                self.mbc.write_ram(&mut self.ram, address, value);
+                self.ram_dirty = true;
+            }
+            _ => panic!("Unexpected Cartridge::write_byte {} {}", address, value),
+        }
     }
+}
 
-    // just for testing/reading purposes
-    pub fn print_all_bytes(&self) {
-        let mut i = 1;
-        for byte in self.bytes.iter() {
-            println!("byte {i}: {byte}");
-            i += 1;
+// Fallback for unflushed ram writes when flush_save never got called, errors go nowhere here
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        if self.ram_dirty && self.save_path.is_some() {
+            let _ = self.flush_save();
         }
     }
 }
+
+// Zeroed image with the given header codes, the header checksum is left to the caller
+#[cfg(test)]
+fn blank_test_rom(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Vec<u8> {
+    let mut rom = vec![0; (2 * ROM_BANK_SIZE) << rom_size_code];
+    rom[CARTRIDGE_TYPE_ADDRESS] = cartridge_type;
+    rom[ROM_SIZE_ADDRESS] = rom_size_code;
+    rom[RAM_SIZE_ADDRESS] = ram_size_code;
+    rom
+}
+
+// Valid image with the given header codes, for any test in the crate that needs a cartridge
+#[cfg(test)]
+pub(crate) fn build_test_rom(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Vec<u8> {
+    let mut rom = blank_test_rom(cartridge_type, rom_size_code, ram_size_code);
+    rom[HEADER_CHECKSUM_ADDRESS] = CartridgeHeader::calculate_header_checksum(&rom);
+    rom
+}
+
+// Temp dir path tagged with the process id, so concurrent test runs don't share files
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("emoboy_{}_{}", std::process::id(), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 32 KiB rom-only image with a title, destination and version
+    fn titled_test_rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = blank_test_rom(0x00, 0x00, 0x00);
+        rom[TITLE_START..TITLE_START + title.len()].copy_from_slice(title);
+        rom[DESTINATION_CODE_ADDRESS] = 0x01;
+        rom[VERSION_ADDRESS] = 0x02;
+        rom[HEADER_CHECKSUM_ADDRESS] = CartridgeHeader::calculate_header_checksum(&rom);
+        rom
+    }
+
+    #[test]
+    fn parse_dmg_header() {
+        let rom = titled_test_rom(b"TETRIS");
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.manufacturer_code, "");
+        assert_eq!(header.cgb_support(), CgbSupport::DmgOnly);
+        assert!(!header.sgb_support());
+        assert_eq!(header.cartridge_type_name(), "ROM ONLY");
+        assert_eq!(header.rom_size_bytes(), Some(0x8000));
+        assert_eq!(header.rom_bank_count(), Some(2));
+        assert_eq!(header.ram_size_bytes(), 0);
+        assert_eq!(header.destination(), Destination::Overseas);
+        assert_eq!(header.version, 0x02);
+    }
+
+    #[test]
+    fn parse_cgb_header_with_manufacturer_code() {
+        let mut rom = titled_test_rom(b"POKEMON_SLVAAXE");
+        rom[CGB_FLAG_ADDRESS] = 0x80;
+        rom[SGB_FLAG_ADDRESS] = 0x03;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer_code, "AAXE");
+        assert_eq!(header.cgb_support(), CgbSupport::CgbEnhanced);
+        assert!(header.sgb_support());
+    }
+
+    #[test]
+    fn load_valid_rom() {
+        let rom = titled_test_rom(b"TEST");
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+
+        assert_eq!(cartridge.header.as_ref().unwrap().title, "TEST");
+    }
+
+    #[test]
+    fn reject_bad_header_checksum() {
+        let mut rom = titled_test_rom(b"TEST");
+        rom[HEADER_CHECKSUM_ADDRESS] = rom[HEADER_CHECKSUM_ADDRESS].wrapping_add(1);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::HeaderChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn reject_rom_size_mismatch() {
+        let mut rom = titled_test_rom(b"TEST");
+        rom.truncate(ROM_BANK_SIZE);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::RomSizeMismatch {
+                declared: 0x8000,
+                actual: 0x4000
+            })
+        ));
+    }
+
+    #[test]
+    fn reject_unsupported_cartridge_type() {
+        let rom = build_test_rom(0xFC, 0x00, 0x00);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::UnsupportedCartridgeType(0xFC))
+        ));
+    }
+
+    #[test]
+    fn writes_to_rom_do_not_change_rom() {
+        let mut rom = titled_test_rom(b"TEST");
+        rom[0x0150] = 0x12;
+        let mut cartridge = Cartridge::from_bytes(rom).unwrap();
+
+        cartridge.write_byte(0x0150, 0x34);
+
+        assert_eq!(cartridge.read_byte(0x0150), 0x12);
+    }
+
+    #[test]
+    fn global_checksum_skips_its_own_bytes() {
+        let mut rom = titled_test_rom(b"TEST");
+        let checksum = CartridgeHeader::calculate_global_checksum(&rom);
+        rom[GLOBAL_CHECKSUM_HIGH_ADDRESS] = (checksum >> 8) as u8;
+        rom[GLOBAL_CHECKSUM_LOW_ADDRESS] = checksum as u8;
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert!(cartridge.global_checksum_valid());
+    }
+
+    #[test]
+    fn save_data_round_trip_with_rtc() {
+        let rom = build_test_rom(0x10, 0x00, 0x02); // MBC3+TIMER+RAM+BATTERY
+
+        let mut cartridge = Cartridge::from_bytes(rom.clone()).unwrap();
+        cartridge.write_byte(0x0000, 0x0A);
+        cartridge.write_byte(0xA000, 0x42);
+        cartridge.step(1_048_576 * 2);
+
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), RAM_BANK_SIZE + RTC_SAVE_SIZE);
+
+        let mut restored = Cartridge::from_bytes(rom).unwrap();
+        restored.load_save_data(&data);
+        restored.write_byte(0x0000, 0x0A);
+        assert_eq!(restored.read_byte(0xA000), 0x42);
+
+        restored.write_byte(0x6000, 0x00);
+        restored.write_byte(0x6000, 0x01);
+        restored.write_byte(0x4000, 0x08);
+        assert_eq!(restored.read_byte(0xA000), 2);
+    }
+
+    #[test]
+    fn battery_ram_persists_to_sav_file() {
+        let directory = test_path("sav_test");
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("battery.gb");
+        let save_path = directory.join("battery.sav");
+
+        let rom = build_test_rom(0x03, 0x00, 0x02); // MBC1+RAM+BATTERY
+        fs::write(&rom_path, &rom).unwrap();
+
+        let mut cartridge = Cartridge::new();
+        cartridge.load_rom_file(rom_path.to_str().unwrap()).unwrap();
+        assert_eq!(cartridge.save_path(), Some(save_path.as_path()));
+
+        cartridge.write_byte(0x0000, 0x0A);
+        cartridge.write_byte(0xA123, 0x99);
+        cartridge.step(SAVE_FLUSH_INTERVAL_M_CYCLES);
+
+        let saved = fs::read(&save_path).unwrap();
+        assert_eq!(saved.len(), RAM_BANK_SIZE);
+        assert_eq!(saved[0x0123], 0x99);
+
+        let mut reloaded = Cartridge::new();
+        reloaded.load_rom_file(rom_path.to_str().unwrap()).unwrap();
+        reloaded.write_byte(0x0000, 0x0A);
+        assert_eq!(reloaded.read_byte(0xA123), 0x99);
+
+        drop(cartridge);
+        drop(reloaded);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn clean_cartridge_only_saves_when_flushed() {
+        let directory = test_path("flush_test");
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("clock.gb");
+        let save_path = directory.join("clock.sav");
+
+        let rom = build_test_rom(0x10, 0x00, 0x02); // MBC3+TIMER+RAM+BATTERY
+        fs::write(&rom_path, &rom).unwrap();
+
+        let mut cartridge = Cartridge::new();
+        cartridge.load_rom_file(rom_path.to_str().unwrap()).unwrap();
+        drop(cartridge);
+        assert!(!save_path.exists());
+
+        let mut cartridge = Cartridge::new();
+        cartridge.load_rom_file(rom_path.to_str().unwrap()).unwrap();
+        cartridge.flush_save().unwrap();
+        assert_eq!(
+            fs::read(&save_path).unwrap().len(),
+            RAM_BANK_SIZE + RTC_SAVE_SIZE
+        );
+
+        drop(cartridge);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn host_synced_rtc_catches_up_on_load() {
+        let directory = test_path("rtc_test");
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("clock.gb");
+
+        let rom = build_test_rom(0x10, 0x00, 0x02); // MBC3+TIMER+RAM+BATTERY
+        fs::write(&rom_path, &rom).unwrap();
+
+        // Saved two hours and a bit ago
+        let mut save = Cartridge::from_bytes(rom).unwrap().save_data();
+        let saved_at = &mut save[RAM_BANK_SIZE + 40..RAM_BANK_SIZE + 48];
+        let timestamp = u64::from_le_bytes(saved_at.try_into().unwrap()) - 2 * 60 * 60 - 30;
+        saved_at.copy_from_slice(&timestamp.to_le_bytes());
+        fs::write(directory.join("clock.sav"), &save).unwrap();
+
+        let mut cartridge = Cartridge::new();
+        cartridge.set_rtc_sync_to_host(true);
+        cartridge.load_rom_file(rom_path.to_str().unwrap()).unwrap();
+
+        cartridge.write_byte(0x0000, 0x0A);
+        cartridge.write_byte(0x6000, 0x00);
+        cartridge.write_byte(0x6000, 0x01);
+        cartridge.write_byte(0x4000, 0x0A); // Hours
+        assert_eq!(cartridge.read_byte(0xA000), 2);
+        cartridge.write_byte(0x4000, 0x09); // Minutes
+        assert_eq!(cartridge.read_byte(0xA000), 0);
+
+        drop(cartridge);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 pub struct Clock {
-    t_cycles: u32,
-    m_cycles: u32,
+    t_cycles: u64,
+    m_cycles: u64,
 }
 
 // Master Clock Speed => 4.194304 MHz
@@ -24,8 +24,16 @@ impl Clock {
             );
         }
 
-        self.t_cycles += cycles * 4;
-        self.m_cycles += cycles;
+        self.t_cycles += cycles as u64 * 4;
+        self.m_cycles += cycles as u64;
+    }
+
+    pub fn t_cycles(&self) -> u64 {
+        self.t_cycles
+    }
+
+    pub fn m_cycles(&self) -> u64 {
+        self.m_cycles
     }
 
     pub fn reset_clock(&mut self) {
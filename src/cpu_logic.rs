@@ -7,7 +7,6 @@ use crate::registers::{RegByte, RegFlag, RegWord};
 
 // TODO: Add OpCodes. Refactor tests/build new ones. Implement clock cycles for each OpCode/fix clock cyles.
 // TODO: Increment Program Counter properly!!! Only stack related operations currently interact with program counter
-// TODO: Might need to fix ime related commands because they are supposed to go into effect: AFTER the NEXT instruction
 // TODO: !!!Rewrite with motherboard instead of motherboard.!!!
 
 pub fn execute_one_byte_opcode(motherboard: &mut motherboard::Motherboard, code: OneByteOpCode) {
@@ -689,9 +688,17 @@ pub fn execute_one_byte_opcode(motherboard: &mut motherboard::Motherboard, code:
             motherboard.clock.cycle_clock(2);
         }
         OneByteOpCode::HALT => {
-            // TODO: Implement halt. Needs IME flag and state of pending interrupts ([IE] & [IF] status)
-            // TODO: Talk with tint about how to handle unimplemnted instructions (panic fine?)
-            panic!("ERROR::Attempted to perform a HALT. Not yet implemented. ");
+            let interrupt_pending = motherboard.memory.interrupts.pending() != 0;
+
+            // With IME off and an interrupt already waiting the cpu never actually halts, and
+            // the byte after HALT gets read twice (https://gbdev.io/pandocs/halt.html#halt-bug)
+            if !motherboard.registers.read_ime() && interrupt_pending {
+                motherboard.halt_bug = true;
+            } else {
+                motherboard.halted = true;
+            }
+
+            motherboard.clock.cycle_clock(1);
         }
         OneByteOpCode::LD_HLcontents_A => {
             let byte: u8 = get_byte_from_8bit_register(motherboard, &RegByte::A);
@@ -1331,7 +1338,7 @@ pub fn execute_one_byte_opcode(motherboard: &mut motherboard::Motherboard, code:
             motherboard.clock.cycle_clock(2);
         }
         OneByteOpCode::EI => {
-            motherboard.registers.write_ime(true);
+            motherboard.registers.schedule_ime();
             motherboard.clock.cycle_clock(1);
         }
         OneByteOpCode::RST_38H => {
@@ -1359,7 +1366,10 @@ pub fn execute_two_byte_opcode(
         }
         // 1x
         TwoByteOpCode::STOP => {
-            // TODO: Look into more later
+            // Low power mode until a button is pressed, DIV is reset on the way in
+            motherboard.stopped = true;
+            motherboard.memory.write_byte(0xFF04, 0x00);
+            motherboard.clock.cycle_clock(1);
         }
         TwoByteOpCode::LD_D_N8 => {
             motherboard.registers.write_byte(&RegByte::D, byte1);
@@ -1639,10 +1649,8 @@ pub fn execute_three_byte_opcode(
                 return;
             }
 
-            let ret_address = motherboard
-                .registers
-                .read_word(&RegWord::PC)
-                .wrapping_add(1);
+            // Operands are already fetched, PC points at the next instruction
+            let ret_address = motherboard.registers.read_word(&RegWord::PC);
 
             let ret_address_high = ((ret_address >> 8) & 0xFF) as u8;
             let ret_address_low = (ret_address & 0xFF) as u8;
@@ -1673,10 +1681,8 @@ pub fn execute_three_byte_opcode(
                 return;
             }
 
-            let ret_address = motherboard
-                .registers
-                .read_word(&RegWord::PC)
-                .wrapping_add(1);
+            // Operands are already fetched, PC points at the next instruction
+            let ret_address = motherboard.registers.read_word(&RegWord::PC);
 
             let ret_address_high = ((ret_address >> 8) & 0xFF) as u8;
             let ret_address_low = (ret_address & 0xFF) as u8;
@@ -1692,10 +1698,8 @@ pub fn execute_three_byte_opcode(
             motherboard.clock.cycle_clock(6);
         }
         ThreeByteOpCode::CALL_A16 => {
-            let ret_address = motherboard
-                .registers
-                .read_word(&RegWord::PC)
-                .wrapping_add(1);
+            // Operands are already fetched, PC points at the next instruction
+            let ret_address = motherboard.registers.read_word(&RegWord::PC);
 
             let ret_address_high = ((ret_address >> 8) & 0xFF) as u8;
             let ret_address_low = (ret_address & 0xFF) as u8;
@@ -1727,10 +1731,8 @@ pub fn execute_three_byte_opcode(
                 return;
             }
 
-            let ret_address = motherboard
-                .registers
-                .read_word(&RegWord::PC)
-                .wrapping_add(1);
+            // Operands are already fetched, PC points at the next instruction
+            let ret_address = motherboard.registers.read_word(&RegWord::PC);
 
             let ret_address_high = ((ret_address >> 8) & 0xFF) as u8;
             let ret_address_low = (ret_address & 0xFF) as u8;
@@ -1761,10 +1763,8 @@ pub fn execute_three_byte_opcode(
                 return;
             }
 
-            let ret_address = motherboard
-                .registers
-                .read_word(&RegWord::PC)
-                .wrapping_add(1);
+            // Operands are already fetched, PC points at the next instruction
+            let ret_address = motherboard.registers.read_word(&RegWord::PC);
 
             let ret_address_high = ((ret_address >> 8) & 0xFF) as u8;
             let ret_address_low = (ret_address & 0xFF) as u8;
@@ -3339,8 +3339,7 @@ pub fn return_call(motherboard: &mut motherboard::Motherboard) {
 }
 
 pub fn fast_reset_to_address(motherboard: &mut motherboard::Motherboard, address: u16) {
-    motherboard.registers.increment_pc();
-
+    // PC was already moved past the RST when it was fetched
     let [msb, lsb] = motherboard.registers.read_word(&RegWord::PC).to_be_bytes();
 
     // Load High then Low Byte into stack
@@ -0,0 +1,552 @@
+use std::io::{self, BufRead, Write};
+
+use crate::disasm;
+use crate::memory::{Access, WatchHit};
+use crate::motherboard::Motherboard;
+use crate::registers::RegWord;
+use crate::symbols::Symbols;
+
+const DEFAULT_LIST_LENGTH: usize = 5;
+const DEFAULT_DUMP_LENGTH: u32 = 0x40;
+const DUMP_BYTES_PER_LINE: u32 = 16;
+// Banked labels past this are ram, which breaks whatever bank is mapped
+const ROM_END: u16 = 0x7FFF;
+
+const HELP: &str = "\
+step [n]              (s) Run n instructions, 1 by default
+next                  (n) Step, running through CALLs and RSTs until they return
+continue              (c) Run until a breakpoint or watchpoint
+break [address]       (b) Break before executing address, lists everything without one
+delete <address>      (d) Remove a breakpoint
+watch <address> [rw]  (w) Stop on reads (r), writes (w, default) or both (rw) of address
+unwatch <address>     Remove the watchpoints on address
+regs                  (r) Registers and flags
+x <address> [length]  Hexdump memory
+list [n]              (l) The next n instructions from PC
+quit                  (q)
+An empty line repeats the last command. Addresses are hex or labels from the .sym file.";
+
+// Labels in rom only break while their bank is mapped, a hex address breaks in every bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub bank: Option<u16>,
+}
+
+impl Breakpoint {
+    pub fn is_hit(&self, motherboard: &Motherboard, pc: u16) -> bool {
+        pc == self.address
+            && self
+                .bank
+                .is_none_or(|bank| motherboard.memory.cartridge.rom_bank(pc) == bank as usize)
+    }
+
+    // A breakpoint without a bank goes for every breakpoint at its address
+    fn covers(&self, other: &Breakpoint) -> bool {
+        self.address == other.address && (self.bank.is_none() || self.bank == other.bank)
+    }
+}
+
+// Why a run came back to the prompt
+enum Stop {
+    Done,
+    Breakpoint(u16),
+    Watchpoint(Vec<WatchHit>),
+}
+
+// Line based debugger, everything runs through Motherboard::perform_one_operation
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            last_command: String::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    // Reads commands until quit or the input runs out
+    pub fn run(
+        &mut self,
+        motherboard: &mut Motherboard,
+        mut input: impl BufRead,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        print_location(motherboard, output)?;
+
+        loop {
+            write!(output, "(emoboy) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            if !self.execute(motherboard, &line, output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // False on quit
+    fn execute(
+        &mut self,
+        motherboard: &mut Motherboard,
+        line: &str,
+        output: &mut impl Write,
+    ) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        let result = match command {
+            "s" | "step" => parse_count(arguments.first(), 1).map(|count| {
+                let mut stop = Stop::Done;
+                for _ in 0..count {
+                    stop = self.run_until(motherboard, |_| true);
+                    if !matches!(stop, Stop::Done) {
+                        break;
+                    }
+                }
+                Some(stop)
+            }),
+            "n" | "next" => Ok(Some(self.step_over(motherboard))),
+            "c" | "continue" => Ok(Some(self.run_until(motherboard, |_| false))),
+            "b" | "break" => match arguments.first() {
+                Some(value) => resolve_breakpoint(&motherboard.symbols, value).map(|breakpoint| {
+                    self.add_breakpoint(breakpoint);
+                    None
+                }),
+                None => {
+                    self.print_breakpoints(motherboard, output)?;
+                    Ok(None)
+                }
+            },
+            "d" | "delete" => {
+                required_breakpoint(&motherboard.symbols, arguments.first()).map(|deleted| {
+                    self.breakpoints
+                        .retain(|breakpoint| !deleted.covers(breakpoint));
+                    None
+                })
+            }
+            "w" | "watch" => {
+                required_address(&motherboard.symbols, arguments.first()).and_then(|address| {
+                    let accesses: &[Access] = match arguments.get(1).copied().unwrap_or("w") {
+                        "r" => &[Access::Read],
+                        "w" => &[Access::Write],
+                        "rw" => &[Access::Read, Access::Write],
+                        other => return Err(format!("Expected r, w or rw, got {}", other)),
+                    };
+                    for access in accesses {
+                        motherboard.memory.add_watchpoint(address, *access);
+                    }
+                    Ok(None)
+                })
+            }
+            "unwatch" => required_address(&motherboard.symbols, arguments.first()).map(|address| {
+                motherboard.memory.remove_watchpoints(address);
+                None
+            }),
+            "r" | "regs" => {
+                writeln!(output, "{}", motherboard.registers.dump())?;
+                Ok(None)
+            }
+            "x" => required_address(&motherboard.symbols, arguments.first()).and_then(|address| {
+                let length = parse_count(arguments.get(1), DEFAULT_DUMP_LENGTH as usize)?;
+                hexdump(motherboard, address, length as u32, output).map_err(|e| e.to_string())?;
+                Ok(None)
+            }),
+            "l" | "list" => parse_count(arguments.first(), DEFAULT_LIST_LENGTH).and_then(|count| {
+                list(motherboard, count, output).map_err(|e| e.to_string())?;
+                Ok(None)
+            }),
+            "h" | "help" => {
+                writeln!(output, "{}", HELP)?;
+                Ok(None)
+            }
+            "q" | "quit" => return Ok(false),
+            _ => Err(format!("Unknown command {}, try help", command)),
+        };
+
+        match result {
+            Ok(Some(stop)) => {
+                match stop {
+                    Stop::Done => {}
+                    Stop::Breakpoint(address) => {
+                        writeln!(output, "Breakpoint at {}", describe(motherboard, address))?
+                    }
+                    Stop::Watchpoint(hits) => {
+                        for hit in hits {
+                            let access = match hit.access {
+                                Access::Read => "Read",
+                                Access::Write => "Write",
+                            };
+                            writeln!(
+                                output,
+                                "{} {:02X} at {}",
+                                access,
+                                hit.value,
+                                describe(motherboard, hit.address)
+                            )?;
+                        }
+                    }
+                }
+                print_location(motherboard, output)?;
+            }
+            Ok(None) => {}
+            Err(message) => writeln!(output, "{}", message)?,
+        }
+        Ok(true)
+    }
+
+    // Always runs at least one instruction, so continuing from a breakpoint moves off it
+    fn run_until(
+        &self,
+        motherboard: &mut Motherboard,
+        mut done: impl FnMut(&Motherboard) -> bool,
+    ) -> Stop {
+        loop {
+            motherboard.perform_one_operation();
+
+            let hits = motherboard.memory.take_watch_hits();
+            if !hits.is_empty() {
+                return Stop::Watchpoint(hits);
+            }
+            let pc = motherboard.registers.read_word(&RegWord::PC);
+            if self
+                .breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.is_hit(motherboard, pc))
+            {
+                return Stop::Breakpoint(pc);
+            }
+            if done(motherboard) {
+                return Stop::Done;
+            }
+        }
+    }
+
+    // Anything that pushes a return address runs until it's back, a recursive call to the same
+    // spot doesn't count since SP is still below where it started
+    fn step_over(&self, motherboard: &mut Motherboard) -> Stop {
+        let pc = motherboard.registers.read_word(&RegWord::PC);
+        let sp = motherboard.registers.read_word(&RegWord::SP);
+        let opcode = motherboard.memory.peek_byte(pc);
+
+        let is_call = matches!(opcode, 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC);
+        let is_rst = opcode & 0b1100_0111 == 0b1100_0111;
+        if !is_call && !is_rst {
+            return self.run_until(motherboard, |_| true);
+        }
+
+        let return_address = pc.wrapping_add(Motherboard::get_instruction_length(opcode) as u16);
+        self.run_until(motherboard, |motherboard| {
+            motherboard.registers.read_word(&RegWord::PC) == return_address
+                && motherboard.registers.read_word(&RegWord::SP) >= sp
+        })
+    }
+
+    fn print_breakpoints(
+        &self,
+        motherboard: &Motherboard,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        for breakpoint in &self.breakpoints {
+            let description = match breakpoint.bank {
+                Some(bank) => {
+                    let name = motherboard.symbols.label(Some(bank), breakpoint.address);
+                    format!(
+                        "{:02X}:{:04X} ({})",
+                        bank,
+                        breakpoint.address,
+                        name.unwrap_or("?")
+                    )
+                }
+                None => describe(motherboard, breakpoint.address),
+            };
+            writeln!(output, "Breakpoint {}", description)?;
+        }
+        for (address, access) in motherboard.memory.watchpoints() {
+            let access = match access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            writeln!(
+                output,
+                "Watchpoint {} {}",
+                describe(motherboard, *address),
+                access
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn print_location(motherboard: &Motherboard, output: &mut impl Write) -> io::Result<()> {
+    list(motherboard, 1, output)
+}
+
+// Disassembly of each instruction from PC on
+fn list(motherboard: &Motherboard, count: usize, output: &mut impl Write) -> io::Result<()> {
+    let mut address = motherboard.registers.read_word(&RegWord::PC);
+
+    for _ in 0..count {
+        if let Some(name) = motherboard.label_at(address) {
+            writeln!(output, "{}:", name)?;
+        }
+
+        let instruction = disasm::decode(
+            |at| motherboard.memory.peek_byte(at),
+            address,
+            |at| motherboard.label_at(at),
+        );
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        writeln!(
+            output,
+            "{:04X}: {:<9} {}",
+            address,
+            bytes.join(" "),
+            instruction.text
+        )?;
+
+        address = address.wrapping_add(instruction.bytes.len() as u16);
+    }
+    Ok(())
+}
+
+fn hexdump(
+    motherboard: &Motherboard,
+    start: u16,
+    length: u32,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let end = (start as u32 + length).min(0x10000);
+
+    for line_start in (start as u32..end).step_by(DUMP_BYTES_PER_LINE as usize) {
+        let line_end = (line_start + DUMP_BYTES_PER_LINE).min(end);
+        let bytes: Vec<String> = (line_start..line_end)
+            .map(|address| format!("{:02X}", motherboard.memory.peek_byte(address as u16)))
+            .collect();
+        writeln!(output, "{:04X}: {}", line_start, bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+// 0150, 0x0150 and $0150 all work
+pub fn parse_address(value: &str) -> Result<u16, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('$'))
+        .unwrap_or(value);
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Expected a hex address, got {}", value))
+}
+
+// Labels take priority, a label like Add would otherwise read as hex
+pub fn resolve_address(symbols: &Symbols, value: &str) -> Result<u16, String> {
+    symbols
+        .address(value)
+        .map_or_else(|| parse_address(value), Ok)
+        .map_err(|_| format!("Expected a hex address or label, got {}", value))
+}
+
+// Keeps the bank of a label in rom so it only breaks in that bank
+pub fn resolve_breakpoint(symbols: &Symbols, value: &str) -> Result<Breakpoint, String> {
+    match symbols.location(value) {
+        Some((bank, address)) => Ok(Breakpoint {
+            address,
+            bank: (address <= ROM_END).then_some(bank),
+        }),
+        None => resolve_address(symbols, value).map(|address| Breakpoint {
+            address,
+            bank: None,
+        }),
+    }
+}
+
+fn required_address(symbols: &Symbols, value: Option<&&str>) -> Result<u16, String> {
+    resolve_address(symbols, value.ok_or("Expected an address")?)
+}
+
+fn required_breakpoint(symbols: &Symbols, value: Option<&&str>) -> Result<Breakpoint, String> {
+    resolve_breakpoint(symbols, value.ok_or("Expected an address")?)
+}
+
+// C010, or C010 (Label) if there's one for it
+fn describe(motherboard: &Motherboard, address: u16) -> String {
+    match motherboard.label_at(address) {
+        Some(name) => format!("{:04X} ({})", address, name),
+        None => format!("{:04X}", address),
+    }
+}
+
+fn parse_count(value: Option<&&str>, default: usize) -> Result<usize, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Expected a number, got {}", value)),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::cartridge::{Cartridge, build_test_rom};
+
+    use super::*;
+
+    // LD A,0x42; CALL 0xC010; LD (0xC100),A; JR -2 ... 0xC010: INC A; RET
+    fn debug_motherboard() -> Motherboard {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC000);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFFE);
+
+        let program = [0x3E, 0x42, 0xCD, 0x10, 0xC0, 0xEA, 0x00, 0xC1, 0x18, 0xFE];
+        for (offset, byte) in program.iter().enumerate() {
+            motherboard.memory.write_byte(0xC000 + offset as u16, *byte);
+        }
+        motherboard.memory.write_byte(0xC010, 0x3C);
+        motherboard.memory.write_byte(0xC011, 0xC9);
+        motherboard
+    }
+
+    fn session(motherboard: &mut Motherboard, commands: &str) -> String {
+        let mut output = Vec::new();
+        Debugger::new()
+            .run(motherboard, Cursor::new(commands), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_and_list() {
+        let mut motherboard = debug_motherboard();
+        let output = session(&mut motherboard, "step\nlist 2\n");
+
+        assert!(output.starts_with("C000: 3E 42     ld a, $42\n"));
+        assert!(output.contains("C002: CD 10 C0  call $C010\nC005: EA 00 C1  ld [$C100], a\n"));
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC002);
+    }
+
+    #[test]
+    fn next_runs_through_call() {
+        let mut motherboard = debug_motherboard();
+        session(&mut motherboard, "s\nn\n");
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC005);
+        assert_eq!(motherboard.registers.read_word(&RegWord::AF) >> 8, 0x43);
+    }
+
+    #[test]
+    fn step_into_call_then_empty_line_repeats() {
+        let mut motherboard = debug_motherboard();
+        session(&mut motherboard, "s\n\n\n");
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC011);
+    }
+
+    #[test]
+    fn continue_to_breakpoint() {
+        let mut motherboard = debug_motherboard();
+        let output = session(&mut motherboard, "b c010\nc\nregs\nq\nstep\n");
+
+        assert!(output.contains("Breakpoint at C010\nC010: 3C        inc a\n"));
+        assert!(output.contains("AF: 42"));
+        assert!(output.contains("SP: DFFC"));
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC010);
+    }
+
+    #[test]
+    fn continue_to_watchpoint() {
+        let mut motherboard = debug_motherboard();
+        let output = session(&mut motherboard, "watch c100\nb\nc\n");
+
+        assert!(output.contains("Watchpoint C100 write\n"));
+        assert!(output.contains("Write 43 at C100\nC008: 18 FE     jr $C008\n"));
+    }
+
+    #[test]
+    fn labels_for_breakpoints_and_listing() {
+        let mut motherboard = debug_motherboard();
+        motherboard.symbols =
+            Symbols::parse("00:c000 Main\n00:c010 Increment\n00:c100 wResult\n").unwrap();
+        let output = session(&mut motherboard, "l 3\nb Increment\nc\nb\nb Nowhere\n");
+
+        assert!(output.starts_with("Main:\nC000: 3E 42     ld a, $42\n(emoboy) Main:\n"));
+        assert!(
+            output.contains("C002: CD 10 C0  call Increment\nC005: EA 00 C1  ld [wResult], a\n")
+        );
+        assert!(
+            output.contains("Breakpoint at C010 (Increment)\nIncrement:\nC010: 3C        inc a\n")
+        );
+        assert!(output.contains("Breakpoint C010 (Increment)\n"));
+        assert!(output.contains("Expected a hex address or label, got Nowhere\n"));
+    }
+
+    #[test]
+    fn label_breakpoints_keep_their_bank() {
+        let mut rom = build_test_rom(0x01, 0x01, 0x00); // 64 KiB MBC1
+        // LD A,1; LD (0x2000),A; CALL 0x4000; the same again for bank 2; JR -2
+        let program = [
+            0x3E, 0x01, 0xEA, 0x00, 0x20, 0xCD, 0x00, 0x40, 0x3E, 0x02, 0xEA, 0x00, 0x20, 0xCD,
+            0x00, 0x40, 0x18, 0xFE,
+        ];
+        rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+        rom[0x4000] = 0xC9;
+        rom[0x8000] = 0xC9;
+
+        let mut motherboard = Motherboard::new();
+        motherboard.memory.cartridge = Cartridge::from_bytes(rom).unwrap();
+        motherboard.registers.write_word(&RegWord::PC, 0x0150);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFFE);
+        motherboard.symbols = Symbols::parse("01:4000 BankOne\n02:4000 BankTwo\n").unwrap();
+        let output = session(&mut motherboard, "b BankTwo\nb\nc\nd 4000\nb\n");
+
+        assert!(output.contains("Breakpoint at 4000 (BankTwo)\n"));
+        assert_eq!(output.matches("Breakpoint 02:4000 (BankTwo)\n").count(), 1);
+        assert_eq!(motherboard.registers.read_word(&RegWord::AF) >> 8, 0x02);
+        assert_eq!(motherboard.memory.cartridge.rom_bank(0x4000), 2);
+    }
+
+    #[test]
+    fn hexdump_and_errors() {
+        let mut motherboard = debug_motherboard();
+        let output = session(&mut motherboard, "x c000 18\nx\nbogus\n");
+
+        assert!(
+            output.contains("C000: 3E 42 CD 10 C0 EA 00 C1 18 FE 00 00 00 00 00 00\nC010: 3C C9\n")
+        );
+        assert!(output.contains("Expected an address\n"));
+        assert!(output.contains("Unknown command bogus, try help\n"));
+    }
+}
@@ -0,0 +1,811 @@
+use crate::motherboard::Motherboard;
+use crate::opcode::{OneByteOpCode, PrefixOpCode, ThreeByteOpCode, TwoByteOpCode, UNUSED_OPCODES};
+use crate::symbols::Symbols;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const PREFIX: u8 = 0xCB;
+
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    // RGBDS syntax, e.g. "ld a, [hl+]" or "jr nz, $0150"
+    pub text: String,
+}
+
+// For when there's no symbol file
+pub fn no_labels(_address: u16) -> Option<&'static str> {
+    None
+}
+
+// Decodes the instruction at address. Reads go through a closure so this works on a rom slice as
+// well as live memory, and label names any jump target or memory operand it has a label for.
+pub fn decode<'a>(
+    read: impl Fn(u16) -> u8,
+    address: u16,
+    label: impl Fn(u16) -> Option<&'a str>,
+) -> Instruction {
+    let opcode = read(address);
+    let length = Motherboard::get_instruction_length(opcode) as u16;
+    let bytes: Vec<u8> = (0..length)
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+
+    Instruction {
+        address,
+        text: mnemonic(&bytes, address, &label),
+        bytes,
+    }
+}
+
+// Everything in bytes as if it were loaded at origin. An instruction cut off by the end comes
+// out as db.
+pub fn disassemble<'a>(
+    bytes: &[u8],
+    origin: u16,
+    label: impl Fn(u16) -> Option<&'a str>,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let length = Motherboard::get_instruction_length(bytes[offset]) as usize;
+
+        let instruction = if offset + length <= bytes.len() {
+            decode(
+                |at| bytes[at.wrapping_sub(origin) as usize],
+                address,
+                &label,
+            )
+        } else {
+            Instruction {
+                address,
+                bytes: vec![bytes[offset]],
+                text: format!("db ${:02X}", bytes[offset]),
+            }
+        };
+
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+// The whole rom, one section per 16 KiB bank at the address it gets mapped to. Labels in the
+// switchable range are looked up in the bank being disassembled.
+pub fn disassemble_rom(rom: &[u8], symbols: &Symbols) -> String {
+    let mut output = String::new();
+
+    for (bank, bank_bytes) in rom.chunks(ROM_BANK_SIZE).enumerate() {
+        if bank == 0 {
+            output.push_str("SECTION \"ROM Bank $000\", ROM0[$0000]\n");
+        } else {
+            output.push_str(&format!(
+                "\nSECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:X}]\n",
+                bank, bank
+            ));
+        }
+
+        let origin = if bank == 0 { 0x0000 } else { 0x4000 };
+        let label = |address: u16| {
+            let label_bank = match address {
+                0x0000..=0x3FFF => Some(0),
+                0x4000..=0x7FFF => Some(bank as u16),
+                _ => None,
+            };
+            symbols.label(label_bank, address)
+        };
+
+        for instruction in disassemble(bank_bytes, origin, label) {
+            if let Some(name) = label(instruction.address) {
+                output.push_str(&format!("{}:\n", name));
+            }
+            output.push_str(&format_line(&instruction));
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+// Mnemonic with the address and raw bytes in a comment
+pub fn format_line(instruction: &Instruction) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    format!(
+        "    {:<24}; ${:04X}: {}",
+        instruction.text,
+        instruction.address,
+        bytes.join(" ")
+    )
+}
+
+// Goes through the same opcode enums the cpu executes, so both agree on what every byte means
+fn mnemonic<'a>(bytes: &[u8], address: u16, label: &impl Fn(u16) -> Option<&'a str>) -> String {
+    let opcode = bytes[0];
+    if UNUSED_OPCODES.contains(&opcode) {
+        return format!("db ${:02X}", opcode);
+    }
+
+    let n8 = || format!("${:02X}", bytes[1]);
+    let n16 = || format!("${:04X}", u16::from_le_bytes([bytes[1], bytes[2]]));
+    let target = |target: u16| match label(target) {
+        Some(name) => name.to_string(),
+        None => format!("${:04X}", target),
+    };
+    let a16 = || target(u16::from_le_bytes([bytes[1], bytes[2]]));
+    let a8 = || target(0xFF00 | bytes[1] as u16);
+    let e8 = || target(address.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16));
+
+    match bytes.len() {
+        1 => one_byte_mnemonic(OneByteOpCode::from(opcode)).to_string(),
+        2 if opcode == PREFIX => prefix_mnemonic(PrefixOpCode::from(bytes[1])).to_string(),
+        2 => match TwoByteOpCode::from(opcode) {
+            TwoByteOpCode::LD_B_N8 => format!("ld b, {}", n8()),
+            TwoByteOpCode::LD_C_N8 => format!("ld c, {}", n8()),
+            TwoByteOpCode::STOP => "stop".to_string(),
+            TwoByteOpCode::LD_D_N8 => format!("ld d, {}", n8()),
+            TwoByteOpCode::JR_R8 => format!("jr {}", e8()),
+            TwoByteOpCode::LD_E_N8 => format!("ld e, {}", n8()),
+            TwoByteOpCode::JR_NZ_R8 => format!("jr nz, {}", e8()),
+            TwoByteOpCode::LD_H_N8 => format!("ld h, {}", n8()),
+            TwoByteOpCode::JR_Z_R8 => format!("jr z, {}", e8()),
+            TwoByteOpCode::LD_L_N8 => format!("ld l, {}", n8()),
+            TwoByteOpCode::JR_NC_R8 => format!("jr nc, {}", e8()),
+            TwoByteOpCode::LD_HLcontents_N8 => format!("ld [hl], {}", n8()),
+            TwoByteOpCode::JR_C_R8 => format!("jr c, {}", e8()),
+            TwoByteOpCode::LD_A_N8 => format!("ld a, {}", n8()),
+            TwoByteOpCode::ADD_A_N8 => format!("add a, {}", n8()),
+            TwoByteOpCode::ADC_A_N8 => format!("adc a, {}", n8()),
+            TwoByteOpCode::SUB_N8 => format!("sub a, {}", n8()),
+            TwoByteOpCode::SBC_A_N8 => format!("sbc a, {}", n8()),
+            TwoByteOpCode::LDH_A8contents_A => format!("ldh [{}], a", a8()),
+            TwoByteOpCode::AND_N8 => format!("and a, {}", n8()),
+            TwoByteOpCode::ADD_SP_R8 => format!("add sp, {}", signed(bytes[1], "")),
+            TwoByteOpCode::XOR_N8 => format!("xor a, {}", n8()),
+            TwoByteOpCode::LDH_A_A8contents => format!("ldh a, [{}]", a8()),
+            TwoByteOpCode::OR_N8 => format!("or a, {}", n8()),
+            TwoByteOpCode::LD_HL_SPplusR8 => format!("ld hl, sp{}", signed(bytes[1], "+")),
+            TwoByteOpCode::CP_N8 => format!("cp a, {}", n8()),
+        },
+        _ => match ThreeByteOpCode::from(opcode) {
+            ThreeByteOpCode::LD_BC_D16 => format!("ld bc, {}", n16()),
+            ThreeByteOpCode::LD_A16contents_SP => format!("ld [{}], sp", a16()),
+            ThreeByteOpCode::LD_DE_D16 => format!("ld de, {}", n16()),
+            ThreeByteOpCode::LD_HL_D16 => format!("ld hl, {}", n16()),
+            ThreeByteOpCode::LD_SP_D16 => format!("ld sp, {}", n16()),
+            ThreeByteOpCode::JP_NZ_A16 => format!("jp nz, {}", a16()),
+            ThreeByteOpCode::JP_A16 => format!("jp {}", a16()),
+            ThreeByteOpCode::CALL_NZ_A16 => format!("call nz, {}", a16()),
+            ThreeByteOpCode::JP_Z_A16 => format!("jp z, {}", a16()),
+            ThreeByteOpCode::CALL_Z_A16 => format!("call z, {}", a16()),
+            ThreeByteOpCode::CALL_A16 => format!("call {}", a16()),
+            ThreeByteOpCode::JP_NC_A16 => format!("jp nc, {}", a16()),
+            ThreeByteOpCode::CALL_NC_A16 => format!("call nc, {}", a16()),
+            ThreeByteOpCode::JP_C_16 => format!("jp c, {}", a16()),
+            ThreeByteOpCode::CALL_C_A16 => format!("call c, {}", a16()),
+            ThreeByteOpCode::LD_A16contents_A => format!("ld [{}], a", a16()),
+            ThreeByteOpCode::LD_A_A16contents => format!("ld a, [{}]", a16()),
+        },
+    }
+}
+
+fn one_byte_mnemonic(opcode: OneByteOpCode) -> &'static str {
+    match opcode {
+        OneByteOpCode::NOP => "nop",
+        OneByteOpCode::LD_BCcontents_A => "ld [bc], a",
+        OneByteOpCode::INC_BC => "inc bc",
+        OneByteOpCode::INC_B => "inc b",
+        OneByteOpCode::DEC_B => "dec b",
+        OneByteOpCode::RLCA => "rlca",
+        OneByteOpCode::ADD_HL_BC => "add hl, bc",
+        OneByteOpCode::LD_A_BCcontents => "ld a, [bc]",
+        OneByteOpCode::DEC_BC => "dec bc",
+        OneByteOpCode::INC_C => "inc c",
+        OneByteOpCode::DEC_C => "dec c",
+        OneByteOpCode::RRCA => "rrca",
+        OneByteOpCode::LD_DEcontents_A => "ld [de], a",
+        OneByteOpCode::INC_DE => "inc de",
+        OneByteOpCode::INC_D => "inc d",
+        OneByteOpCode::DEC_D => "dec d",
+        OneByteOpCode::RLA => "rla",
+        OneByteOpCode::ADD_HL_DE => "add hl, de",
+        OneByteOpCode::LD_A_DEcontents => "ld a, [de]",
+        OneByteOpCode::DEC_DE => "dec de",
+        OneByteOpCode::INC_E => "inc e",
+        OneByteOpCode::DEC_E => "dec e",
+        OneByteOpCode::RRA => "rra",
+        OneByteOpCode::LD_HLincrementedcontents_A => "ld [hl+], a",
+        OneByteOpCode::INC_HL => "inc hl",
+        OneByteOpCode::INC_H => "inc h",
+        OneByteOpCode::DEC_H => "dec h",
+        OneByteOpCode::DAA => "daa",
+        OneByteOpCode::ADD_HL_HL => "add hl, hl",
+        OneByteOpCode::LD_A_HLincrementedcontents => "ld a, [hl+]",
+        OneByteOpCode::DEC_HL => "dec hl",
+        OneByteOpCode::INC_L => "inc l",
+        OneByteOpCode::DEC_L => "dec l",
+        OneByteOpCode::CPL => "cpl",
+        OneByteOpCode::LD_HLdecrementedcontents_A => "ld [hl-], a",
+        OneByteOpCode::INC_SP => "inc sp",
+        OneByteOpCode::INC_HLcontents => "inc [hl]",
+        OneByteOpCode::DEC_HLcontents => "dec [hl]",
+        OneByteOpCode::SCF => "scf",
+        OneByteOpCode::ADD_HL_SP => "add hl, sp",
+        OneByteOpCode::LD_A_HLdecrementedcontents => "ld a, [hl-]",
+        OneByteOpCode::DEC_SP => "dec sp",
+        OneByteOpCode::INC_A => "inc a",
+        OneByteOpCode::DEC_A => "dec a",
+        OneByteOpCode::CCF => "ccf",
+        OneByteOpCode::LD_B_B => "ld b, b",
+        OneByteOpCode::LD_B_C => "ld b, c",
+        OneByteOpCode::LD_B_D => "ld b, d",
+        OneByteOpCode::LD_B_E => "ld b, e",
+        OneByteOpCode::LD_B_H => "ld b, h",
+        OneByteOpCode::LD_B_L => "ld b, l",
+        OneByteOpCode::LD_B_HLcontents => "ld b, [hl]",
+        OneByteOpCode::LD_B_A => "ld b, a",
+        OneByteOpCode::LD_C_B => "ld c, b",
+        OneByteOpCode::LD_C_C => "ld c, c",
+        OneByteOpCode::LD_C_D => "ld c, d",
+        OneByteOpCode::LD_C_E => "ld c, e",
+        OneByteOpCode::LD_C_H => "ld c, h",
+        OneByteOpCode::LD_C_L => "ld c, l",
+        OneByteOpCode::LD_C_HLcontents => "ld c, [hl]",
+        OneByteOpCode::LD_C_A => "ld c, a",
+        OneByteOpCode::LD_D_B => "ld d, b",
+        OneByteOpCode::LD_D_C => "ld d, c",
+        OneByteOpCode::LD_D_D => "ld d, d",
+        OneByteOpCode::LD_D_E => "ld d, e",
+        OneByteOpCode::LD_D_H => "ld d, h",
+        OneByteOpCode::LD_D_L => "ld d, l",
+        OneByteOpCode::LD_D_HLcontents => "ld d, [hl]",
+        OneByteOpCode::LD_D_A => "ld d, a",
+        OneByteOpCode::LD_E_B => "ld e, b",
+        OneByteOpCode::LD_E_C => "ld e, c",
+        OneByteOpCode::LD_E_D => "ld e, d",
+        OneByteOpCode::LD_E_E => "ld e, e",
+        OneByteOpCode::LD_E_H => "ld e, h",
+        OneByteOpCode::LD_E_L => "ld e, l",
+        OneByteOpCode::LD_E_HLcontents => "ld e, [hl]",
+        OneByteOpCode::LD_E_A => "ld e, a",
+        OneByteOpCode::LD_H_B => "ld h, b",
+        OneByteOpCode::LD_H_C => "ld h, c",
+        OneByteOpCode::LD_H_D => "ld h, d",
+        OneByteOpCode::LD_H_E => "ld h, e",
+        OneByteOpCode::LD_H_H => "ld h, h",
+        OneByteOpCode::LD_H_L => "ld h, l",
+        OneByteOpCode::LD_H_HLcontents => "ld h, [hl]",
+        OneByteOpCode::LD_H_A => "ld h, a",
+        OneByteOpCode::LD_L_B => "ld l, b",
+        OneByteOpCode::LD_L_C => "ld l, c",
+        OneByteOpCode::LD_L_D => "ld l, d",
+        OneByteOpCode::LD_L_E => "ld l, e",
+        OneByteOpCode::LD_L_H => "ld l, h",
+        OneByteOpCode::LD_L_L => "ld l, l",
+        OneByteOpCode::LD_L_HLcontents => "ld l, [hl]",
+        OneByteOpCode::LD_L_A => "ld l, a",
+        OneByteOpCode::LD_HLcontents_B => "ld [hl], b",
+        OneByteOpCode::LD_HLcontents_C => "ld [hl], c",
+        OneByteOpCode::LD_HLcontents_D => "ld [hl], d",
+        OneByteOpCode::LD_HLcontents_E => "ld [hl], e",
+        OneByteOpCode::LD_HLcontents_H => "ld [hl], h",
+        OneByteOpCode::LD_HLcontents_L => "ld [hl], l",
+        OneByteOpCode::HALT => "halt",
+        OneByteOpCode::LD_HLcontents_A => "ld [hl], a",
+        OneByteOpCode::LD_A_B => "ld a, b",
+        OneByteOpCode::LD_A_C => "ld a, c",
+        OneByteOpCode::LD_A_D => "ld a, d",
+        OneByteOpCode::LD_A_E => "ld a, e",
+        OneByteOpCode::LD_A_H => "ld a, h",
+        OneByteOpCode::LD_A_L => "ld a, l",
+        OneByteOpCode::LD_A_HLcontents => "ld a, [hl]",
+        OneByteOpCode::LD_A_A => "ld a, a",
+        OneByteOpCode::ADD_A_B => "add a, b",
+        OneByteOpCode::ADD_A_C => "add a, c",
+        OneByteOpCode::ADD_A_D => "add a, d",
+        OneByteOpCode::ADD_A_E => "add a, e",
+        OneByteOpCode::ADD_A_H => "add a, h",
+        OneByteOpCode::ADD_A_L => "add a, l",
+        OneByteOpCode::ADD_A_HLcontents => "add a, [hl]",
+        OneByteOpCode::ADD_A_A => "add a, a",
+        OneByteOpCode::ADC_A_B => "adc a, b",
+        OneByteOpCode::ADC_A_C => "adc a, c",
+        OneByteOpCode::ADC_A_D => "adc a, d",
+        OneByteOpCode::ADC_A_E => "adc a, e",
+        OneByteOpCode::ADC_A_H => "adc a, h",
+        OneByteOpCode::ADC_A_L => "adc a, l",
+        OneByteOpCode::ADC_A_HLcontents => "adc a, [hl]",
+        OneByteOpCode::ADC_A_A => "adc a, a",
+        OneByteOpCode::SUB_A_B => "sub a, b",
+        OneByteOpCode::SUB_A_C => "sub a, c",
+        OneByteOpCode::SUB_A_D => "sub a, d",
+        OneByteOpCode::SUB_A_E => "sub a, e",
+        OneByteOpCode::SUB_A_H => "sub a, h",
+        OneByteOpCode::SUB_A_L => "sub a, l",
+        OneByteOpCode::SUB_A_HLcontents => "sub a, [hl]",
+        OneByteOpCode::SUB_A_A => "sub a, a",
+        OneByteOpCode::SBC_A_B => "sbc a, b",
+        OneByteOpCode::SBC_A_C => "sbc a, c",
+        OneByteOpCode::SBC_A_D => "sbc a, d",
+        OneByteOpCode::SBC_A_E => "sbc a, e",
+        OneByteOpCode::SBC_A_H => "sbc a, h",
+        OneByteOpCode::SBC_A_L => "sbc a, l",
+        OneByteOpCode::SBC_A_HLcontents => "sbc a, [hl]",
+        OneByteOpCode::SBC_A_A => "sbc a, a",
+        OneByteOpCode::AND_B => "and a, b",
+        OneByteOpCode::AND_C => "and a, c",
+        OneByteOpCode::AND_D => "and a, d",
+        OneByteOpCode::AND_E => "and a, e",
+        OneByteOpCode::AND_H => "and a, h",
+        OneByteOpCode::AND_L => "and a, l",
+        OneByteOpCode::AND_HLcontents => "and a, [hl]",
+        OneByteOpCode::AND_A => "and a, a",
+        OneByteOpCode::XOR_B => "xor a, b",
+        OneByteOpCode::XOR_C => "xor a, c",
+        OneByteOpCode::XOR_D => "xor a, d",
+        OneByteOpCode::XOR_E => "xor a, e",
+        OneByteOpCode::XOR_H => "xor a, h",
+        OneByteOpCode::XOR_L => "xor a, l",
+        OneByteOpCode::XOR_HLcontents => "xor a, [hl]",
+        OneByteOpCode::XOR_A => "xor a, a",
+        OneByteOpCode::OR_B => "or a, b",
+        OneByteOpCode::OR_C => "or a, c",
+        OneByteOpCode::OR_D => "or a, d",
+        OneByteOpCode::OR_E => "or a, e",
+        OneByteOpCode::OR_H => "or a, h",
+        OneByteOpCode::OR_L => "or a, l",
+        OneByteOpCode::OR_HLcontents => "or a, [hl]",
+        OneByteOpCode::OR_A => "or a, a",
+        OneByteOpCode::CP_B => "cp a, b",
+        OneByteOpCode::CP_C => "cp a, c",
+        OneByteOpCode::CP_D => "cp a, d",
+        OneByteOpCode::CP_E => "cp a, e",
+        OneByteOpCode::CP_H => "cp a, h",
+        OneByteOpCode::CP_L => "cp a, l",
+        OneByteOpCode::CP_HLcontents => "cp a, [hl]",
+        OneByteOpCode::CP_A => "cp a, a",
+        OneByteOpCode::RET_NZ => "ret nz",
+        OneByteOpCode::POP_BC => "pop bc",
+        OneByteOpCode::PUSH_BC => "push bc",
+        OneByteOpCode::RST_00H => "rst $00",
+        OneByteOpCode::RET_Z => "ret z",
+        OneByteOpCode::RET => "ret",
+        OneByteOpCode::RST_08H => "rst $08",
+        OneByteOpCode::RET_NC => "ret nc",
+        OneByteOpCode::POP_DE => "pop de",
+        OneByteOpCode::PUSH_DE => "push de",
+        OneByteOpCode::RST_10H => "rst $10",
+        OneByteOpCode::RET_C => "ret c",
+        OneByteOpCode::RETI => "reti",
+        OneByteOpCode::RST_18H => "rst $18",
+        OneByteOpCode::POP_HL => "pop hl",
+        OneByteOpCode::LD_Ccontents_A => "ldh [c], a",
+        OneByteOpCode::PUSH_HL => "push hl",
+        OneByteOpCode::RST_20H => "rst $20",
+        OneByteOpCode::JP_HLcontents => "jp hl",
+        OneByteOpCode::RST_28H => "rst $28",
+        OneByteOpCode::POP_AF => "pop af",
+        OneByteOpCode::LD_A_Ccontents => "ldh a, [c]",
+        OneByteOpCode::DI => "di",
+        OneByteOpCode::PUSH_AF => "push af",
+        OneByteOpCode::RST_30H => "rst $30",
+        OneByteOpCode::LD_SP_HL => "ld sp, hl",
+        OneByteOpCode::EI => "ei",
+        OneByteOpCode::RST_38H => "rst $38",
+    }
+}
+
+fn prefix_mnemonic(opcode: PrefixOpCode) -> &'static str {
+    match opcode {
+        PrefixOpCode::RLC_B => "rlc b",
+        PrefixOpCode::RLC_C => "rlc c",
+        PrefixOpCode::RLC_D => "rlc d",
+        PrefixOpCode::RLC_E => "rlc e",
+        PrefixOpCode::RLC_H => "rlc h",
+        PrefixOpCode::RLC_L => "rlc l",
+        PrefixOpCode::RLC_HLcontents => "rlc [hl]",
+        PrefixOpCode::RLC_A => "rlc a",
+        PrefixOpCode::RRC_B => "rrc b",
+        PrefixOpCode::RRC_C => "rrc c",
+        PrefixOpCode::RRC_D => "rrc d",
+        PrefixOpCode::RRC_E => "rrc e",
+        PrefixOpCode::RRC_H => "rrc h",
+        PrefixOpCode::RRC_L => "rrc l",
+        PrefixOpCode::RRC_HLcontents => "rrc [hl]",
+        PrefixOpCode::RRC_A => "rrc a",
+        PrefixOpCode::RL_B => "rl b",
+        PrefixOpCode::RL_C => "rl c",
+        PrefixOpCode::RL_D => "rl d",
+        PrefixOpCode::RL_E => "rl e",
+        PrefixOpCode::RL_H => "rl h",
+        PrefixOpCode::RL_L => "rl l",
+        PrefixOpCode::RL_HLcontents => "rl [hl]",
+        PrefixOpCode::RL_A => "rl a",
+        PrefixOpCode::RR_B => "rr b",
+        PrefixOpCode::RR_C => "rr c",
+        PrefixOpCode::RR_D => "rr d",
+        PrefixOpCode::RR_E => "rr e",
+        PrefixOpCode::RR_H => "rr h",
+        PrefixOpCode::RR_L => "rr l",
+        PrefixOpCode::RR_HLcontents => "rr [hl]",
+        PrefixOpCode::RR_A => "rr a",
+        PrefixOpCode::SLA_B => "sla b",
+        PrefixOpCode::SLA_C => "sla c",
+        PrefixOpCode::SLA_D => "sla d",
+        PrefixOpCode::SLA_E => "sla e",
+        PrefixOpCode::SLA_H => "sla h",
+        PrefixOpCode::SLA_L => "sla l",
+        PrefixOpCode::SLA_HLcontents => "sla [hl]",
+        PrefixOpCode::SLA_A => "sla a",
+        PrefixOpCode::SRA_B => "sra b",
+        PrefixOpCode::SRA_C => "sra c",
+        PrefixOpCode::SRA_D => "sra d",
+        PrefixOpCode::SRA_E => "sra e",
+        PrefixOpCode::SRA_H => "sra h",
+        PrefixOpCode::SRA_L => "sra l",
+        PrefixOpCode::SRA_HLcontents => "sra [hl]",
+        PrefixOpCode::SRA_A => "sra a",
+        PrefixOpCode::SWAP_B => "swap b",
+        PrefixOpCode::SWAP_C => "swap c",
+        PrefixOpCode::SWAP_D => "swap d",
+        PrefixOpCode::SWAP_E => "swap e",
+        PrefixOpCode::SWAP_H => "swap h",
+        PrefixOpCode::SWAP_L => "swap l",
+        PrefixOpCode::SWAP_HLcontents => "swap [hl]",
+        PrefixOpCode::SWAP_A => "swap a",
+        PrefixOpCode::SRL_B => "srl b",
+        PrefixOpCode::SRL_C => "srl c",
+        PrefixOpCode::SRL_D => "srl d",
+        PrefixOpCode::SRL_E => "srl e",
+        PrefixOpCode::SRL_H => "srl h",
+        PrefixOpCode::SRL_L => "srl l",
+        PrefixOpCode::SRL_HLcontents => "srl [hl]",
+        PrefixOpCode::SRL_A => "srl a",
+        PrefixOpCode::BIT_0_B => "bit 0, b",
+        PrefixOpCode::BIT_0_C => "bit 0, c",
+        PrefixOpCode::BIT_0_D => "bit 0, d",
+        PrefixOpCode::BIT_0_E => "bit 0, e",
+        PrefixOpCode::BIT_0_H => "bit 0, h",
+        PrefixOpCode::BIT_0_L => "bit 0, l",
+        PrefixOpCode::BIT_0_HLcontents => "bit 0, [hl]",
+        PrefixOpCode::BIT_0_A => "bit 0, a",
+        PrefixOpCode::BIT_1_B => "bit 1, b",
+        PrefixOpCode::BIT_1_C => "bit 1, c",
+        PrefixOpCode::BIT_1_D => "bit 1, d",
+        PrefixOpCode::BIT_1_E => "bit 1, e",
+        PrefixOpCode::BIT_1_H => "bit 1, h",
+        PrefixOpCode::BIT_1_L => "bit 1, l",
+        PrefixOpCode::BIT_1_HLcontents => "bit 1, [hl]",
+        PrefixOpCode::BIT_1_A => "bit 1, a",
+        PrefixOpCode::BIT_2_B => "bit 2, b",
+        PrefixOpCode::BIT_2_C => "bit 2, c",
+        PrefixOpCode::BIT_2_D => "bit 2, d",
+        PrefixOpCode::BIT_2_E => "bit 2, e",
+        PrefixOpCode::BIT_2_H => "bit 2, h",
+        PrefixOpCode::BIT_2_L => "bit 2, l",
+        PrefixOpCode::BIT_2_HLcontents => "bit 2, [hl]",
+        PrefixOpCode::BIT_2_A => "bit 2, a",
+        PrefixOpCode::BIT_3_B => "bit 3, b",
+        PrefixOpCode::BIT_3_C => "bit 3, c",
+        PrefixOpCode::BIT_3_D => "bit 3, d",
+        PrefixOpCode::BIT_3_E => "bit 3, e",
+        PrefixOpCode::BIT_3_H => "bit 3, h",
+        PrefixOpCode::BIT_3_L => "bit 3, l",
+        PrefixOpCode::BIT_3_HLcontents => "bit 3, [hl]",
+        PrefixOpCode::BIT_3_A => "bit 3, a",
+        PrefixOpCode::BIT_4_B => "bit 4, b",
+        PrefixOpCode::BIT_4_C => "bit 4, c",
+        PrefixOpCode::BIT_4_D => "bit 4, d",
+        PrefixOpCode::BIT_4_E => "bit 4, e",
+        PrefixOpCode::BIT_4_H => "bit 4, h",
+        PrefixOpCode::BIT_4_L => "bit 4, l",
+        PrefixOpCode::BIT_4_HLcontents => "bit 4, [hl]",
+        PrefixOpCode::BIT_4_A => "bit 4, a",
+        PrefixOpCode::BIT_5_B => "bit 5, b",
+        PrefixOpCode::BIT_5_C => "bit 5, c",
+        PrefixOpCode::BIT_5_D => "bit 5, d",
+        PrefixOpCode::BIT_5_E => "bit 5, e",
+        PrefixOpCode::BIT_5_H => "bit 5, h",
+        PrefixOpCode::BIT_5_L => "bit 5, l",
+        PrefixOpCode::BIT_5_HLcontents => "bit 5, [hl]",
+        PrefixOpCode::BIT_5_A => "bit 5, a",
+        PrefixOpCode::BIT_6_B => "bit 6, b",
+        PrefixOpCode::BIT_6_C => "bit 6, c",
+        PrefixOpCode::BIT_6_D => "bit 6, d",
+        PrefixOpCode::BIT_6_E => "bit 6, e",
+        PrefixOpCode::BIT_6_H => "bit 6, h",
+        PrefixOpCode::BIT_6_L => "bit 6, l",
+        PrefixOpCode::BIT_6_HLcontents => "bit 6, [hl]",
+        PrefixOpCode::BIT_6_A => "bit 6, a",
+        PrefixOpCode::BIT_7_B => "bit 7, b",
+        PrefixOpCode::BIT_7_C => "bit 7, c",
+        PrefixOpCode::BIT_7_D => "bit 7, d",
+        PrefixOpCode::BIT_7_E => "bit 7, e",
+        PrefixOpCode::BIT_7_H => "bit 7, h",
+        PrefixOpCode::BIT_7_L => "bit 7, l",
+        PrefixOpCode::BIT_7_HLcontents => "bit 7, [hl]",
+        PrefixOpCode::BIT_7_A => "bit 7, a",
+        PrefixOpCode::RES_0_B => "res 0, b",
+        PrefixOpCode::RES_0_C => "res 0, c",
+        PrefixOpCode::RES_0_D => "res 0, d",
+        PrefixOpCode::RES_0_E => "res 0, e",
+        PrefixOpCode::RES_0_H => "res 0, h",
+        PrefixOpCode::RES_0_L => "res 0, l",
+        PrefixOpCode::RES_0_HLcontents => "res 0, [hl]",
+        PrefixOpCode::RES_0_A => "res 0, a",
+        PrefixOpCode::RES_1_B => "res 1, b",
+        PrefixOpCode::RES_1_C => "res 1, c",
+        PrefixOpCode::RES_1_D => "res 1, d",
+        PrefixOpCode::RES_1_E => "res 1, e",
+        PrefixOpCode::RES_1_H => "res 1, h",
+        PrefixOpCode::RES_1_L => "res 1, l",
+        PrefixOpCode::RES_1_HLcontents => "res 1, [hl]",
+        PrefixOpCode::RES_1_A => "res 1, a",
+        PrefixOpCode::RES_2_B => "res 2, b",
+        PrefixOpCode::RES_2_C => "res 2, c",
+        PrefixOpCode::RES_2_D => "res 2, d",
+        PrefixOpCode::RES_2_E => "res 2, e",
+        PrefixOpCode::RES_2_H => "res 2, h",
+        PrefixOpCode::RES_2_L => "res 2, l",
+        PrefixOpCode::RES_2_HLcontents => "res 2, [hl]",
+        PrefixOpCode::RES_2_A => "res 2, a",
+        PrefixOpCode::RES_3_B => "res 3, b",
+        PrefixOpCode::RES_3_C => "res 3, c",
+        PrefixOpCode::RES_3_D => "res 3, d",
+        PrefixOpCode::RES_3_E => "res 3, e",
+        PrefixOpCode::RES_3_H => "res 3, h",
+        PrefixOpCode::RES_3_L => "res 3, l",
+        PrefixOpCode::RES_3_HLcontents => "res 3, [hl]",
+        PrefixOpCode::RES_3_A => "res 3, a",
+        PrefixOpCode::RES_4_B => "res 4, b",
+        PrefixOpCode::RES_4_C => "res 4, c",
+        PrefixOpCode::RES_4_D => "res 4, d",
+        PrefixOpCode::RES_4_E => "res 4, e",
+        PrefixOpCode::RES_4_H => "res 4, h",
+        PrefixOpCode::RES_4_L => "res 4, l",
+        PrefixOpCode::RES_4_HLcontents => "res 4, [hl]",
+        PrefixOpCode::RES_4_A => "res 4, a",
+        PrefixOpCode::RES_5_B => "res 5, b",
+        PrefixOpCode::RES_5_C => "res 5, c",
+        PrefixOpCode::RES_5_D => "res 5, d",
+        PrefixOpCode::RES_5_E => "res 5, e",
+        PrefixOpCode::RES_5_H => "res 5, h",
+        PrefixOpCode::RES_5_L => "res 5, l",
+        PrefixOpCode::RES_5_HLcontents => "res 5, [hl]",
+        PrefixOpCode::RES_5_A => "res 5, a",
+        PrefixOpCode::RES_6_B => "res 6, b",
+        PrefixOpCode::RES_6_C => "res 6, c",
+        PrefixOpCode::RES_6_D => "res 6, d",
+        PrefixOpCode::RES_6_E => "res 6, e",
+        PrefixOpCode::RES_6_H => "res 6, h",
+        PrefixOpCode::RES_6_L => "res 6, l",
+        PrefixOpCode::RES_6_HLcontents => "res 6, [hl]",
+        PrefixOpCode::RES_6_A => "res 6, a",
+        PrefixOpCode::RES_7_B => "res 7, b",
+        PrefixOpCode::RES_7_C => "res 7, c",
+        PrefixOpCode::RES_7_D => "res 7, d",
+        PrefixOpCode::RES_7_E => "res 7, e",
+        PrefixOpCode::RES_7_H => "res 7, h",
+        PrefixOpCode::RES_7_L => "res 7, l",
+        PrefixOpCode::RES_7_HLcontents => "res 7, [hl]",
+        PrefixOpCode::RES_7_A => "res 7, a",
+        PrefixOpCode::SET_0_B => "set 0, b",
+        PrefixOpCode::SET_0_C => "set 0, c",
+        PrefixOpCode::SET_0_D => "set 0, d",
+        PrefixOpCode::SET_0_E => "set 0, e",
+        PrefixOpCode::SET_0_H => "set 0, h",
+        PrefixOpCode::SET_0_L => "set 0, l",
+        PrefixOpCode::SET_0_HLcontents => "set 0, [hl]",
+        PrefixOpCode::SET_0_A => "set 0, a",
+        PrefixOpCode::SET_1_B => "set 1, b",
+        PrefixOpCode::SET_1_C => "set 1, c",
+        PrefixOpCode::SET_1_D => "set 1, d",
+        PrefixOpCode::SET_1_E => "set 1, e",
+        PrefixOpCode::SET_1_H => "set 1, h",
+        PrefixOpCode::SET_1_L => "set 1, l",
+        PrefixOpCode::SET_1_HLcontents => "set 1, [hl]",
+        PrefixOpCode::SET_1_A => "set 1, a",
+        PrefixOpCode::SET_2_B => "set 2, b",
+        PrefixOpCode::SET_2_C => "set 2, c",
+        PrefixOpCode::SET_2_D => "set 2, d",
+        PrefixOpCode::SET_2_E => "set 2, e",
+        PrefixOpCode::SET_2_H => "set 2, h",
+        PrefixOpCode::SET_2_L => "set 2, l",
+        PrefixOpCode::SET_2_HLcontents => "set 2, [hl]",
+        PrefixOpCode::SET_2_A => "set 2, a",
+        PrefixOpCode::SET_3_B => "set 3, b",
+        PrefixOpCode::SET_3_C => "set 3, c",
+        PrefixOpCode::SET_3_D => "set 3, d",
+        PrefixOpCode::SET_3_E => "set 3, e",
+        PrefixOpCode::SET_3_H => "set 3, h",
+        PrefixOpCode::SET_3_L => "set 3, l",
+        PrefixOpCode::SET_3_HLcontents => "set 3, [hl]",
+        PrefixOpCode::SET_3_A => "set 3, a",
+        PrefixOpCode::SET_4_B => "set 4, b",
+        PrefixOpCode::SET_4_C => "set 4, c",
+        PrefixOpCode::SET_4_D => "set 4, d",
+        PrefixOpCode::SET_4_E => "set 4, e",
+        PrefixOpCode::SET_4_H => "set 4, h",
+        PrefixOpCode::SET_4_L => "set 4, l",
+        PrefixOpCode::SET_4_HLcontents => "set 4, [hl]",
+        PrefixOpCode::SET_4_A => "set 4, a",
+        PrefixOpCode::SET_5_B => "set 5, b",
+        PrefixOpCode::SET_5_C => "set 5, c",
+        PrefixOpCode::SET_5_D => "set 5, d",
+        PrefixOpCode::SET_5_E => "set 5, e",
+        PrefixOpCode::SET_5_H => "set 5, h",
+        PrefixOpCode::SET_5_L => "set 5, l",
+        PrefixOpCode::SET_5_HLcontents => "set 5, [hl]",
+        PrefixOpCode::SET_5_A => "set 5, a",
+        PrefixOpCode::SET_6_B => "set 6, b",
+        PrefixOpCode::SET_6_C => "set 6, c",
+        PrefixOpCode::SET_6_D => "set 6, d",
+        PrefixOpCode::SET_6_E => "set 6, e",
+        PrefixOpCode::SET_6_H => "set 6, h",
+        PrefixOpCode::SET_6_L => "set 6, l",
+        PrefixOpCode::SET_6_HLcontents => "set 6, [hl]",
+        PrefixOpCode::SET_6_A => "set 6, a",
+        PrefixOpCode::SET_7_B => "set 7, b",
+        PrefixOpCode::SET_7_C => "set 7, c",
+        PrefixOpCode::SET_7_D => "set 7, d",
+        PrefixOpCode::SET_7_E => "set 7, e",
+        PrefixOpCode::SET_7_H => "set 7, h",
+        PrefixOpCode::SET_7_L => "set 7, l",
+        PrefixOpCode::SET_7_HLcontents => "set 7, [hl]",
+        PrefixOpCode::SET_7_A => "set 7, a",
+    }
+}
+
+// $05 / -$03, sign is what goes in front of positive values
+fn signed(byte: u8, sign: &str) -> String {
+    let value = byte as i8;
+    if value < 0 {
+        format!("-${:02X}", value.unsigned_abs())
+    } else {
+        format!("{}${:02X}", sign, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8], address: u16) -> String {
+        disassemble(bytes, address, no_labels).remove(0).text
+    }
+
+    #[test]
+    fn immediates_and_registers() {
+        assert_eq!(text(&[0x00], 0), "nop");
+        assert_eq!(text(&[0x01, 0x34, 0x12], 0), "ld bc, $1234");
+        assert_eq!(text(&[0x08, 0x00, 0xC0], 0), "ld [$C000], sp");
+        assert_eq!(text(&[0x22], 0), "ld [hl+], a");
+        assert_eq!(text(&[0x3A], 0), "ld a, [hl-]");
+        assert_eq!(text(&[0x36, 0x42], 0), "ld [hl], $42");
+        assert_eq!(text(&[0x41], 0), "ld b, c");
+        assert_eq!(text(&[0x76], 0), "halt");
+        assert_eq!(text(&[0x96], 0), "sub a, [hl]");
+        assert_eq!(text(&[0xFE, 0x90], 0), "cp a, $90");
+        assert_eq!(text(&[0xF5], 0), "push af");
+        assert_eq!(text(&[0xE9], 0), "jp hl");
+        assert_eq!(text(&[0xFF], 0), "rst $38");
+        assert_eq!(text(&[0x10, 0x00], 0), "stop");
+    }
+
+    #[test]
+    fn high_ram_and_stack_pointer_forms() {
+        assert_eq!(text(&[0xE0, 0x44], 0), "ldh [$FF44], a");
+        assert_eq!(text(&[0xF0, 0x00], 0), "ldh a, [$FF00]");
+        assert_eq!(text(&[0xE2], 0), "ldh [c], a");
+        assert_eq!(text(&[0xF2], 0), "ldh a, [c]");
+        assert_eq!(text(&[0xEA, 0x00, 0xC1], 0), "ld [$C100], a");
+        assert_eq!(text(&[0xE8, 0xFD], 0), "add sp, -$03");
+        assert_eq!(text(&[0xF8, 0x05], 0), "ld hl, sp+$05");
+        assert_eq!(text(&[0xF8, 0x80], 0), "ld hl, sp-$80");
+    }
+
+    #[test]
+    fn jump_targets() {
+        assert_eq!(text(&[0x18, 0xFE], 0x0150), "jr $0150");
+        assert_eq!(text(&[0x20, 0x10], 0x0150), "jr nz, $0162");
+        assert_eq!(text(&[0xC3, 0x50, 0x01], 0x0100), "jp $0150");
+        assert_eq!(text(&[0xDA, 0x00, 0x40], 0), "jp c, $4000");
+        assert_eq!(text(&[0xCD, 0x10, 0xC0], 0), "call $C010");
+        assert_eq!(text(&[0xC4, 0x10, 0xC0], 0), "call nz, $C010");
+        assert_eq!(text(&[0xD8], 0), "ret c");
+    }
+
+    #[test]
+    fn prefixed() {
+        assert_eq!(text(&[0xCB, 0x00], 0), "rlc b");
+        assert_eq!(text(&[0xCB, 0x37], 0), "swap a");
+        assert_eq!(text(&[0xCB, 0x7C], 0), "bit 7, h");
+        assert_eq!(text(&[0xCB, 0x86], 0), "res 0, [hl]");
+        assert_eq!(text(&[0xCB, 0xFF], 0), "set 7, a");
+    }
+
+    #[test]
+    fn illegal_and_truncated_become_db() {
+        assert_eq!(text(&[0xD3], 0), "db $D3");
+        assert_eq!(text(&[0xFC], 0), "db $FC");
+
+        let instructions = disassemble(&[0x00, 0xC3, 0x50], 0x7FFD, no_labels);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[1].text, "db $C3");
+        assert_eq!(instructions[2].address, 0x7FFF);
+    }
+
+    #[test]
+    fn lengths_match_instruction_length_table() {
+        for opcode in 0..=0xFF_u8 {
+            let bytes = [opcode, 0x00, 0x00];
+            let instruction = decode(|address| bytes[address as usize], 0, no_labels);
+            assert_eq!(
+                instruction.bytes.len(),
+                Motherboard::get_instruction_length(opcode) as usize
+            );
+            assert!(!instruction.text.is_empty());
+        }
+    }
+
+    #[test]
+    fn rom_sections_per_bank() {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x4000] = 0xC9;
+        let output = disassemble_rom(&rom, &Symbols::new());
+
+        assert!(output.starts_with("SECTION \"ROM Bank $000\", ROM0[$0000]\n    nop"));
+        assert!(output.contains(
+            "SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$1]\n    ret                     ; $4000: C9\n"
+        ));
+    }
+
+    #[test]
+    fn labels_replace_addresses() {
+        let symbols =
+            Symbols::parse("00:0150 Main\n00:0153 Main.loop\n00:c000 wCounter\n00:ff80 hFlag\n")
+                .unwrap();
+        let label = |address| symbols.label(None, address);
+
+        let code = [
+            0xC3, 0x53, 0x01, 0x18, 0xFE, 0xEA, 0x00, 0xC0, 0xE0, 0x80, 0x21, 0x00, 0xC0,
+        ];
+        let texts: Vec<String> = disassemble(&code, 0x0150, label)
+            .into_iter()
+            .map(|instruction| instruction.text)
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "jp Main.loop",
+                "jr Main.loop",
+                "ld [wCounter], a",
+                "ldh [hFlag], a",
+                "ld hl, $C000"
+            ]
+        );
+    }
+
+    #[test]
+    fn rom_labels_follow_the_bank() {
+        let mut rom = vec![0x00; 0xC000];
+        // call $4000 from bank 0, then a ret at the start of banks 1 and 2
+        rom[0x0000] = 0xCD;
+        rom[0x0001] = 0x00;
+        rom[0x0002] = 0x40;
+        rom[0x4000] = 0xC9;
+        rom[0x8000] = 0xC9;
+        let symbols = Symbols::parse("01:4000 BankOne\n02:4000 BankTwo\n").unwrap();
+        let output = disassemble_rom(&rom, &symbols);
+
+        // Bank 0 can't know what's mapped at $4000
+        assert!(output.contains("    call $4000 "));
+        assert!(output.contains("BANK[$1]\nBankOne:\n    ret"));
+        assert!(output.contains("BANK[$2]\nBankTwo:\n    ret"));
+    }
+}
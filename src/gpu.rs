@@ -1,30 +1,60 @@
-const VRAM_START: u16 = 0x8000;
-const VRAM_END: u16 = 0x9FFF;
+use crate::interrupts::{Interrupt, Interrupts};
+
+mod pixel_fifo;
+
+use pixel_fifo::PixelFifo;
+
+pub const VRAM_START: u16 = 0x8000;
+pub const VRAM_END: u16 = 0x9FFF;
 const VRAM_SIZE: usize = ((VRAM_END - VRAM_START) + 1) as usize;
-const OAM_START: u16 = 0xFE00;
-const OAM_END: u16 = 0xFE9F;
+pub const OAM_START: u16 = 0xFE00;
+pub const OAM_END: u16 = 0xFE9F;
 const OAM_SIZE: usize = ((OAM_END - OAM_START) + 1) as usize;
-const LCD_CONTROL_ADDRESS: u16 = 0xFF40;
-const LCD_STATUS_ADDRESS: u16 = 0xFF41;
+pub const LCD_CONTROL_ADDRESS: u16 = 0xFF40;
+pub const LCD_STATUS_ADDRESS: u16 = 0xFF41;
 const BACKGROUND_Y_ADDRESS: u16 = 0xFF42;
 const BACKGROUND_X_ADDRESS: u16 = 0xFF43;
-const LCD_Y_ADDRESS: u16 = 0xFF44;
-const LCD_Y_COMPARE_ADDRESS: u16 = 0xFF45;
-const OAM_DMA_SOURCE_ADDRESS: u16 = 0xFF46;
+pub const LCD_Y_ADDRESS: u16 = 0xFF44;
+pub const LCD_Y_COMPARE_ADDRESS: u16 = 0xFF45;
+pub const OAM_DMA_SOURCE_ADDRESS: u16 = 0xFF46;
 const PALETTE_BG_ADDRESS: u16 = 0xFF47;
 const PALETTE_OBJ_0_ADDRESS: u16 = 0xFF48;
 const PALETTE_OBJ_1_ADDRESS: u16 = 0xFF49;
 const WINDOW_Y_ADDRESS: u16 = 0xFF4A;
-const WINDOW_X_ADDRESS: u16 = 0xFF4B;
+pub const WINDOW_X_ADDRESS: u16 = 0xFF4B;
+
+const NUM_CYCLES_OAM: u32 = 80;
+const NUM_CYCLES_DRAW: u32 = 172;
+const NUM_CYCLES_HBLANK: u32 = 204;
+const NUM_CYCLES_VBLANK: u32 = 456;
 
-const NUM_CYCLES_OAM: u16 = 80;
-const NUM_CYCLES_DRAW: u16 = 172;
-const NUM_CYCLES_HBLANK: u16 = 204;
-const NUM_CYCLES_VBLANK: u16 = 456;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
 
-const NUM_VISIBLE_SCANLINES: u8 = 143;
+const TILE_MAP_0: u16 = 0x9800;
+const TILE_MAP_1: u16 = 0x9C00;
+const TILE_DATA_UNSIGNED_BASE: u16 = 0x8000;
+const TILE_DATA_SIGNED_BASE: u16 = 0x9000;
+const TILE_SIZE: u16 = 16;
+
+const MAX_SPRITES_PER_LINE: usize = 10;
+const SPRITE_BG_PRIORITY: u8 = 0b1000_0000;
+const SPRITE_Y_FLIP: u8 = 0b0100_0000;
+const SPRITE_X_FLIP: u8 = 0b0010_0000;
+const SPRITE_PALETTE: u8 = 0b0001_0000;
+
+const NUM_VISIBLE_SCANLINES: u8 = 144;
 const MAX_SCANLINES: u8 = 153;
 
+// Scanline draws a whole line at the end of a fixed length mode 3 and is the fast default.
+// PixelFifo runs the fetchers dot by dot, so mode 3 stretches with scrolling, the window and
+// sprites, and register writes partway through a line show up where they happened.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    Scanline,
+    PixelFifo,
+}
+
 pub struct Gpu {
     vram: [u8; VRAM_SIZE],
     oam: [u8; OAM_SIZE],
@@ -41,7 +71,25 @@ pub struct Gpu {
     window_y: u8,
     window_x: u8,
 
-    clock: u16,
+    clock: u32,
+
+    // Shades 0 (white) to 3 (black), row by row
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // Raw bg/window colour ids of the line being drawn, before the palette
+    line_color_ids: [u8; SCREEN_WIDTH],
+    // Only counts lines the window was actually drawn on, so it picks up where it left off if
+    // it gets switched off partway down the screen
+    window_line: u8,
+
+    // All the enabled STAT sources ORed together. Only a low -> high change requests the
+    // interrupt, so one source can block another that comes on while it's still high.
+    stat_line: bool,
+
+    renderer: Renderer,
+    pixel_fifo: PixelFifo,
+
+    // What LY reads as no matter where the gpu is, for matching logs made that way
+    fixed_lcd_y: Option<u8>,
 }
 
 impl Gpu {
@@ -63,20 +111,34 @@ impl Gpu {
             window_x: 0,
 
             clock: 0,
+
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            line_color_ids: [0; SCREEN_WIDTH],
+            window_line: 0,
+
+            stat_line: false,
+
+            renderer: Renderer::Scanline,
+            pixel_fifo: PixelFifo::new(),
+
+            fixed_lcd_y: None,
         }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             VRAM_START..=VRAM_END => self.vram[(address - VRAM_START) as usize],
-            OAM_START..=OAM_END => self.oam[(address - VRAM_START) as usize],
+            OAM_START..=OAM_END => self.oam[(address - OAM_START) as usize],
             LCD_CONTROL_ADDRESS => self.lcd_control.read_byte(),
             LCD_STATUS_ADDRESS => self.lcd_status.read_byte(),
             BACKGROUND_Y_ADDRESS => self.background_y,
             BACKGROUND_X_ADDRESS => self.background_x,
-            LCD_Y_ADDRESS => self.lcd_y,
+            LCD_Y_ADDRESS => self.fixed_lcd_y.unwrap_or(self.lcd_y),
             LCD_Y_COMPARE_ADDRESS => self.lcd_y_compare,
             OAM_DMA_SOURCE_ADDRESS => self.oam_dma_source,
+            PALETTE_BG_ADDRESS => self.palette_bg,
+            PALETTE_OBJ_0_ADDRESS => self.palette_obj_0,
+            PALETTE_OBJ_1_ADDRESS => self.palette_obj_1,
             WINDOW_Y_ADDRESS => self.window_y,
             WINDOW_X_ADDRESS => self.window_x, // TODO +- 7?
             _ => 0xFF,
@@ -86,14 +148,33 @@ impl Gpu {
     pub fn write_byte(&mut self, address: u16, value: u8) {
         match address {
             VRAM_START..=VRAM_END => self.vram[(address - VRAM_START) as usize] = value,
-            OAM_START..=OAM_END => self.oam[(address - VRAM_START) as usize] = value,
-            LCD_CONTROL_ADDRESS => self.lcd_control.write_byte(value),
+            OAM_START..=OAM_END => self.oam[(address - OAM_START) as usize] = value,
+            LCD_CONTROL_ADDRESS => {
+                let was_enabled = self.lcd_control.enabled;
+                self.lcd_control.write_byte(value);
+
+                // Turning the lcd on starts a fresh frame, turning it off parks it at the top
+                if self.lcd_control.enabled && !was_enabled {
+                    self.clock = 0;
+                    self.pixel_fifo = PixelFifo::new();
+                    self.window_line = 0;
+                    self.lcd_status.mode = Mode::Oam;
+                } else if !self.lcd_control.enabled {
+                    self.clock = 0;
+                    self.pixel_fifo = PixelFifo::new();
+                    self.lcd_status.mode = Mode::Hblank;
+                    self.set_lcd_y(0);
+                }
+            }
             LCD_STATUS_ADDRESS => self.lcd_status.write_byte(value),
             BACKGROUND_Y_ADDRESS => self.background_y = value,
             BACKGROUND_X_ADDRESS => self.background_x = value,
-            LCD_Y_ADDRESS => self.lcd_y = value,
-            LCD_Y_COMPARE_ADDRESS => self.lcd_y_compare = value,
+            LCD_Y_ADDRESS => {} // Read only
+            LCD_Y_COMPARE_ADDRESS => self.set_lcd_y_compare(value),
             OAM_DMA_SOURCE_ADDRESS => self.oam_dma_source = value,
+            PALETTE_BG_ADDRESS => self.palette_bg = value,
+            PALETTE_OBJ_0_ADDRESS => self.palette_obj_0 = value,
+            PALETTE_OBJ_1_ADDRESS => self.palette_obj_1 = value,
             WINDOW_Y_ADDRESS => self.window_y = value,
             WINDOW_X_ADDRESS => self.window_x = value, // TODO +- 7?
             _ => {
@@ -102,55 +183,287 @@ impl Gpu {
         }
     }
 
-    // TODO need to chat with andao about how we want to manage cycles and how close to reality we want timing to be
-    pub fn step(&mut self, num_cycles: u8) {
+    pub fn renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    // Safe to flip at any point, the current line just starts over
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+        self.clock = 0;
+        self.pixel_fifo = PixelFifo::new();
+        if self.lcd_status.mode != Mode::Vblank {
+            self.lcd_status.mode = Mode::Oam;
+        }
+    }
+
+    // Only changes what the cpu reads, the gpu itself keeps counting lines
+    pub fn set_fixed_lcd_y(&mut self, lcd_y: Option<u8>) {
+        self.fixed_lcd_y = lcd_y;
+    }
+
+    // Takes t-cycles, the mode lengths below are all in dots
+    pub fn step(&mut self, t_cycles: u32, interrupts: &mut Interrupts) {
         if !self.lcd_control.enabled {
             return;
         }
 
+        // Picks up anything the cpu changed through STAT or LYC since last time
+        self.update_stat_line(interrupts);
+
+        match self.renderer {
+            Renderer::Scanline => self.step_scanline(t_cycles, interrupts),
+            Renderer::PixelFifo => self.step_pixel_fifo(t_cycles, interrupts),
+        }
+    }
+
+    fn step_scanline(&mut self, t_cycles: u32, interrupts: &mut Interrupts) {
         // clock will tick up but gets reset during every mode switch
-        self.clock += num_cycles as u16;
+        self.clock += t_cycles;
 
-        match self.lcd_status.mode {
-            Mode::Oam => {
-                if self.clock >= NUM_CYCLES_OAM {
+        // A big step can cross more than one mode boundary
+        loop {
+            let mode_length = match self.lcd_status.mode {
+                Mode::Oam => NUM_CYCLES_OAM,
+                Mode::Draw => NUM_CYCLES_DRAW,
+                Mode::Hblank => NUM_CYCLES_HBLANK,
+                Mode::Vblank => NUM_CYCLES_VBLANK,
+            };
+            if self.clock < mode_length {
+                break;
+            }
+            self.clock -= mode_length;
+
+            match self.lcd_status.mode {
+                Mode::Oam => {
                     self.lcd_status.mode = Mode::Draw;
-                    self.clock = self.clock % NUM_CYCLES_OAM;
                 }
-            }
-            Mode::Draw => {
-                if self.clock >= NUM_CYCLES_DRAW {
-                    // draw scanline
+                Mode::Draw => {
+                    self.render_scanline();
                     self.lcd_status.mode = Mode::Hblank;
-                    self.clock = self.clock % NUM_CYCLES_DRAW;
                 }
+                Mode::Hblank | Mode::Vblank => self.next_line(interrupts),
             }
-            Mode::Hblank => {
-                if self.clock >= NUM_CYCLES_HBLANK {
-                    if self.lcd_y >= NUM_VISIBLE_SCANLINES {
-                        self.lcd_status.mode = Mode::Vblank;
-                    } else {
-                        self.set_lcd_y(self.lcd_y + 1);
-                        self.lcd_status.mode = Mode::Oam;
-                    }
-
-                    self.clock = self.clock % NUM_CYCLES_HBLANK;
-                }
+
+            self.update_stat_line(interrupts);
+        }
+    }
+
+    // End of hblank or of a vblank line
+    fn next_line(&mut self, interrupts: &mut Interrupts) {
+        self.set_lcd_y(self.lcd_y + 1);
+
+        if self.lcd_y > MAX_SCANLINES {
+            self.lcd_status.mode = Mode::Oam;
+            self.set_lcd_y(0);
+            self.window_line = 0;
+        } else if self.lcd_y >= NUM_VISIBLE_SCANLINES {
+            if self.lcd_status.mode != Mode::Vblank {
+                self.lcd_status.mode = Mode::Vblank;
+                interrupts.request(Interrupt::VBlank);
             }
-            Mode::Vblank => {
-                if self.clock >= NUM_CYCLES_VBLANK {
-                    self.set_lcd_y(self.lcd_y + 1);
-                    self.clock = self.clock % NUM_CYCLES_VBLANK;
-
-                    if self.lcd_y >= MAX_SCANLINES {
-                        self.lcd_status.mode = Mode::Oam;
-                        self.set_lcd_y(0);
-                    }
+        } else {
+            self.lcd_status.mode = Mode::Oam;
+        }
+    }
+
+    fn update_stat_line(&mut self, interrupts: &mut Interrupts) {
+        let status = &self.lcd_status;
+        let line = (status.coincidence_interrupt && status.coincidence_flag)
+            || match status.mode {
+                Mode::Hblank => status.h_blank_interrupt,
+                Mode::Vblank => status.v_blank_interrupt,
+                Mode::Oam => status.oam_scan_interrupt,
+                Mode::Draw => false,
+            };
+
+        if line && !self.stat_line {
+            interrupts.request(Interrupt::LcdStat);
+        }
+        self.stat_line = line;
+    }
+
+    // The finished picture, SCREEN_WIDTH * SCREEN_HEIGHT shades from 0 (white) to 3 (black)
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn render_scanline(&mut self) {
+        let line = self.lcd_y;
+        let row_start = line as usize * SCREEN_WIDTH;
+
+        // Bit 0 off blanks both the background and the window on DMG
+        let background_enabled = self.lcd_control.background_and_window_enabled;
+        let window_visible = self.window_visible_on(line);
+        let background_map = self.background_map();
+        let window_map = self.window_map();
+
+        for x in 0..SCREEN_WIDTH {
+            // WX is offset by 7, so WX=7 puts the window at the left edge
+            let window_x = x as i16 + 7 - self.window_x as i16;
+
+            let color_id = if !background_enabled {
+                0
+            } else if window_visible && window_x >= 0 {
+                self.tile_color_id(window_map, window_x as u8, self.window_line)
+            } else {
+                // Background map is 256x256 and wraps around
+                let background_x = (x as u8).wrapping_add(self.background_x);
+                let background_y = line.wrapping_add(self.background_y);
+                self.tile_color_id(background_map, background_x, background_y)
+            };
+
+            self.line_color_ids[x] = color_id;
+            self.framebuffer[row_start + x] = apply_palette(self.palette_bg, color_id);
+        }
+
+        if window_visible {
+            self.window_line += 1;
+        }
+
+        if self.lcd_control.sprites_enabled {
+            self.render_sprites(line, row_start);
+        }
+    }
+
+    fn window_visible_on(&self, line: u8) -> bool {
+        self.lcd_control.background_and_window_enabled
+            && self.lcd_control.window_enabled
+            && line >= self.window_y
+            && self.window_x <= 166
+    }
+
+    fn background_map(&self) -> u16 {
+        if self.lcd_control.background_tile_map_select {
+            TILE_MAP_1
+        } else {
+            TILE_MAP_0
+        }
+    }
+
+    fn window_map(&self) -> u16 {
+        if self.lcd_control.window_tile_map {
+            TILE_MAP_1
+        } else {
+            TILE_MAP_0
+        }
+    }
+
+    fn sprite_height(&self) -> i16 {
+        if self.lcd_control.large_sprite_size_enabled {
+            16
+        } else {
+            8
+        }
+    }
+
+    // The first 10 sprites in OAM order that cover this line, whether or not they end up on screen
+    fn scan_oam(&self, line: u8) -> Vec<Sprite> {
+        let height = self.sprite_height();
+
+        self.oam
+            .chunks_exact(4)
+            .map(|entry| Sprite {
+                // OAM positions are offset so sprites can sit partly off the top/left edge
+                y: entry[0] as i16 - 16,
+                x: entry[1] as i16 - 8,
+                tile: entry[2],
+                attributes: entry[3],
+            })
+            .filter(|sprite| (sprite.y..sprite.y + height).contains(&(line as i16)))
+            .take(MAX_SPRITES_PER_LINE)
+            .collect()
+    }
+
+    // Low and high bytes of the sprite row that lands on this line, y flip already applied
+    fn sprite_tile_row(&self, sprite: &Sprite, line: u8) -> (u8, u8) {
+        let height = self.sprite_height();
+        let mut row = line as i16 - sprite.y;
+        if sprite.attributes & SPRITE_Y_FLIP > 0 {
+            row = height - 1 - row;
+        }
+
+        // 8x16 sprites use an even/odd tile pair, bit 0 of the index is ignored
+        let tile = if height == 16 {
+            sprite.tile & 0xFE
+        } else {
+            sprite.tile
+        };
+
+        // Always 0x8000 addressing, rows 8-15 fall through into the next tile
+        let row_address = TILE_DATA_UNSIGNED_BASE + tile as u16 * TILE_SIZE + row as u16 * 2;
+        (self.read_vram(row_address), self.read_vram(row_address + 1))
+    }
+
+    fn sprite_palette(&self, attributes: u8) -> u8 {
+        if attributes & SPRITE_PALETTE > 0 {
+            self.palette_obj_1
+        } else {
+            self.palette_obj_0
+        }
+    }
+
+    fn render_sprites(&mut self, line: u8, row_start: usize) {
+        let mut sprites = self.scan_oam(line);
+
+        // DMG priority: smaller X wins, OAM order breaks ties (sort is stable)
+        sprites.sort_by_key(|sprite| sprite.x);
+
+        for x in 0..SCREEN_WIDTH {
+            // Highest priority non-transparent sprite pixel decides, even if the bg then hides it
+            for sprite in &sprites {
+                let mut column = x as i16 - sprite.x;
+                if !(0..8).contains(&column) {
+                    continue;
+                }
+                if sprite.attributes & SPRITE_X_FLIP > 0 {
+                    column = 7 - column;
+                }
+
+                let (low, high) = self.sprite_tile_row(sprite, line);
+                let color_id = tile_row_color_id(low, high, column as u8);
+
+                // Colour 0 is transparent
+                if color_id == 0 {
+                    continue;
+                }
+
+                if sprite.attributes & SPRITE_BG_PRIORITY == 0 || self.line_color_ids[x] == 0 {
+                    let palette = self.sprite_palette(sprite.attributes);
+                    self.framebuffer[row_start + x] = apply_palette(palette, color_id);
                 }
+                break;
             }
         }
     }
 
+    // Colour id (0-3) of one pixel in a 32x32 tile map
+    fn tile_color_id(&self, map_base: u16, x: u8, y: u8) -> u8 {
+        let map_address = map_base + (y as u16 / 8) * 32 + (x as u16 / 8);
+        let tile_index = self.read_vram(map_address);
+
+        let row_address = self.tile_data_address(tile_index) + (y as u16 % 8) * 2;
+        tile_row_color_id(
+            self.read_vram(row_address),
+            self.read_vram(row_address + 1),
+            x % 8,
+        )
+    }
+
+    // Where a bg/window tile's data starts for the current LCDC addressing mode
+    fn tile_data_address(&self, tile_index: u8) -> u16 {
+        // 0x8000 addressing is unsigned, 0x8800 addressing is signed around 0x9000
+        if self.lcd_control.tile_data_select {
+            TILE_DATA_UNSIGNED_BASE + tile_index as u16 * TILE_SIZE
+        } else {
+            TILE_DATA_SIGNED_BASE.wrapping_add_signed(tile_index as i8 as i16 * TILE_SIZE as i16)
+        }
+    }
+
+    fn read_vram(&self, address: u16) -> u8 {
+        self.vram[(address - VRAM_START) as usize]
+    }
+
     fn set_lcd_y(&mut self, value: u8) {
         self.lcd_y = value;
         self.compare_y_y_compare();
@@ -161,16 +474,33 @@ impl Gpu {
         self.compare_y_y_compare();
     }
 
+    // The STAT interrupt itself is raised from update_stat_line
     fn compare_y_y_compare(&mut self) {
-        let is_equal = self.lcd_y == self.lcd_y_compare;
-        self.lcd_status.coincidence_flag = is_equal;
-        if is_equal {
-            // TODO if enabled a STAT interrupt is requested
-        }
+        self.lcd_status.coincidence_flag = self.lcd_y == self.lcd_y_compare;
     }
 }
 
+// One OAM entry, positions already converted to screen coordinates
 #[derive(Copy, Clone)]
+struct Sprite {
+    y: i16,
+    x: i16,
+    tile: u8,
+    attributes: u8,
+}
+
+// Each tile row is two bytes, low bits then high bits, leftmost pixel in bit 7
+fn tile_row_color_id(low: u8, high: u8, x: u8) -> u8 {
+    let bit = 7 - x;
+    (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01)
+}
+
+// Palettes hold a 2 bit shade for each colour id, id 0 in the bottom bits
+fn apply_palette(palette: u8, color_id: u8) -> u8 {
+    (palette >> (color_id * 2)) & 0b11
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
     Hblank = 0,
     Vblank = 1,
@@ -218,24 +548,16 @@ impl LcdStatus {
             ret = ret | 0b0000_0100
         }
 
-        ret | (self.mode as u8)
+        // Bit 7 is unused and always reads 1
+        0b1000_0000 | ret | (self.mode as u8)
     }
 
+    // Coincidence flag and mode belong to the ppu, only the interrupt selects are writable
     pub fn write_byte(&mut self, data: u8) {
         self.coincidence_interrupt = data & 0b0100_0000 > 0;
         self.oam_scan_interrupt = data & 0b0010_0000 > 0;
         self.v_blank_interrupt = data & 0b0001_0000 > 0;
         self.h_blank_interrupt = data & 0b0000_1000 > 0;
-        self.coincidence_flag = data & 0b0000_0100 > 0;
-
-        let mode_bits = data & 0b0000_0011;
-        match mode_bits {
-            0 => self.mode = Mode::Hblank,
-            1 => self.mode = Mode::Vblank,
-            2 => self.mode = Mode::Oam,
-            3 => self.mode = Mode::Draw,
-            _ => panic!("Unexpected LcdStatus Mode"),
-        }
     }
 }
 
@@ -307,3 +629,548 @@ impl LcdControl {
         self.background_and_window_enabled = data & 0b0000_0001 > 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oam_is_indexed_from_its_own_start() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(OAM_START, 0x12);
+        gpu.write_byte(OAM_END, 0x34);
+
+        assert_eq!(gpu.read_byte(0xFE00), 0x12);
+        assert_eq!(gpu.read_byte(0xFE9F), 0x34);
+        assert_eq!(gpu.read_byte(VRAM_START), 0x00);
+    }
+
+    #[test]
+    fn ly_walks_through_a_frame() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+
+        gpu.step(456, &mut Interrupts::new());
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 1);
+
+        for _ in 1..144 {
+            gpu.step(456, &mut Interrupts::new());
+        }
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 144);
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS) & 0b11, Mode::Vblank as u8);
+
+        for _ in 144..153 {
+            gpu.step(456, &mut Interrupts::new());
+        }
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 153);
+
+        gpu.step(456, &mut Interrupts::new());
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 0);
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS) & 0b11, Mode::Oam as u8);
+    }
+
+    #[test]
+    fn lcd_off_resets_ly() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+        for _ in 0..10 {
+            gpu.step(456, &mut Interrupts::new());
+        }
+
+        gpu.write_byte(LCD_Y_ADDRESS, 0x42);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 10);
+
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x00);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 0);
+        gpu.step(456, &mut Interrupts::new());
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 0);
+    }
+
+    #[test]
+    fn fixed_ly_only_changes_reads() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+        gpu.set_fixed_lcd_y(Some(0x90));
+        for _ in 0..10 {
+            gpu.step(456, &mut Interrupts::new());
+        }
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 0x90);
+
+        gpu.set_fixed_lcd_y(None);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 10);
+    }
+
+    // Fills one row of a tile with the same colour id
+    fn write_tile_row(gpu: &mut Gpu, tile_address: u16, row: u16, color_id: u8) {
+        let low = if color_id & 0b01 > 0 { 0xFF } else { 0x00 };
+        let high = if color_id & 0b10 > 0 { 0xFF } else { 0x00 };
+        gpu.write_byte(tile_address + row * 2, low);
+        gpu.write_byte(tile_address + row * 2 + 1, high);
+    }
+
+    fn write_solid_tile(gpu: &mut Gpu, tile_address: u16, color_id: u8) {
+        for row in 0..8 {
+            write_tile_row(gpu, tile_address, row, color_id);
+        }
+    }
+
+    fn render_frame(gpu: &mut Gpu) {
+        for _ in 0..154 {
+            gpu.step(456, &mut Interrupts::new());
+        }
+    }
+
+    fn pixel(gpu: &Gpu, x: usize, y: usize) -> u8 {
+        gpu.framebuffer()[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn tile_row_bit_order() {
+        // Leftmost pixel is bit 7, high byte is the upper bit of the id
+        assert_eq!(tile_row_color_id(0b1000_0000, 0b0000_0000, 0), 1);
+        assert_eq!(tile_row_color_id(0b0000_0000, 0b1000_0000, 0), 2);
+        assert_eq!(tile_row_color_id(0b0000_0001, 0b0000_0001, 7), 3);
+        assert_eq!(tile_row_color_id(0b0000_0001, 0b0000_0001, 6), 0);
+    }
+
+    #[test]
+    fn background_unsigned_addressing_and_palette() {
+        let mut gpu = Gpu::new();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        gpu.write_byte(0x9801, 0x01);
+        // id 3 -> shade 1, everything else -> shade 0
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b0100_0000);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0001);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 7, 0), 0);
+        assert_eq!(pixel(&gpu, 8, 0), 1);
+        assert_eq!(pixel(&gpu, 15, 7), 1);
+        assert_eq!(pixel(&gpu, 16, 0), 0);
+        assert_eq!(pixel(&gpu, 8, 8), 0);
+    }
+
+    #[test]
+    fn background_signed_addressing() {
+        let mut gpu = Gpu::new();
+        // Tile -1 lives just below 0x9000, tile 0 at 0x9000 rather than 0x8000
+        write_solid_tile(&mut gpu, 0x8FF0, 2);
+        write_solid_tile(&mut gpu, 0x8000, 3);
+        gpu.write_byte(0x9800, 0xFF);
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b1110_0100);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1000_0001);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 2);
+        assert_eq!(pixel(&gpu, 8, 0), 0);
+    }
+
+    #[test]
+    fn background_scroll_wraps() {
+        let mut gpu = Gpu::new();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        // Bottom right corner of the second map
+        gpu.write_byte(0x9FFF, 0x01);
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b1110_0100);
+        gpu.write_byte(BACKGROUND_X_ADDRESS, 252);
+        gpu.write_byte(BACKGROUND_Y_ADDRESS, 252);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_1001);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 3);
+        assert_eq!(pixel(&gpu, 3, 3), 3);
+        assert_eq!(pixel(&gpu, 4, 0), 0);
+        assert_eq!(pixel(&gpu, 0, 4), 0);
+    }
+
+    #[test]
+    fn window_position_and_line_counter() {
+        let mut gpu = Gpu::new();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        // Window uses the second map, only its first row of tiles is set
+        for i in 0..32 {
+            gpu.write_byte(0x9C00 + i, 0x01);
+        }
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b1110_0100);
+        gpu.write_byte(WINDOW_Y_ADDRESS, 20);
+        gpu.write_byte(WINDOW_X_ADDRESS, 47);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1111_0001);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 40, 19), 0);
+        assert_eq!(pixel(&gpu, 39, 20), 0);
+        assert_eq!(pixel(&gpu, 40, 20), 3);
+        assert_eq!(pixel(&gpu, 159, 27), 3);
+        // Ninth window line reads the second tile row
+        assert_eq!(pixel(&gpu, 40, 28), 0);
+    }
+
+    #[test]
+    fn background_disabled_is_blank() {
+        let mut gpu = Gpu::new();
+        write_solid_tile(&mut gpu, 0x8000, 3);
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b1110_0100);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0000);
+
+        render_frame(&mut gpu);
+
+        assert!(gpu.framebuffer().iter().all(|shade| *shade == 0));
+    }
+
+    fn write_sprite(gpu: &mut Gpu, index: u16, y: u8, x: u8, tile: u8, attributes: u8) {
+        let address = OAM_START + index * 4;
+        gpu.write_byte(address, y);
+        gpu.write_byte(address + 1, x);
+        gpu.write_byte(address + 2, tile);
+        gpu.write_byte(address + 3, attributes);
+    }
+
+    // Identity palettes, LCDC is left to each test since they need different sprite and bg bits
+    fn sprite_gpu() -> Gpu {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b1110_0100);
+        gpu.write_byte(PALETTE_OBJ_0_ADDRESS, 0b1110_0100);
+        gpu.write_byte(PALETTE_OBJ_1_ADDRESS, 0b0001_1011);
+        gpu
+    }
+
+    #[test]
+    fn sprite_position_and_palettes() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8010, 1);
+        // Top left corner of the screen is (8, 16) in OAM
+        write_sprite(&mut gpu, 0, 16, 8, 1, 0);
+        write_sprite(&mut gpu, 1, 16, 28, 1, SPRITE_PALETTE);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0011);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 1);
+        assert_eq!(pixel(&gpu, 7, 7), 1);
+        assert_eq!(pixel(&gpu, 8, 0), 0);
+        assert_eq!(pixel(&gpu, 0, 8), 0);
+        assert_eq!(pixel(&gpu, 20, 0), 2);
+    }
+
+    #[test]
+    fn sprites_disabled_are_hidden() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        write_sprite(&mut gpu, 0, 16, 8, 1, 0);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0001);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 0);
+    }
+
+    #[test]
+    fn sprite_flips() {
+        let mut gpu = sprite_gpu();
+        // Only the top row, only the leftmost pixel
+        gpu.write_byte(0x8010, 0b1000_0000);
+        gpu.write_byte(0x8011, 0b1000_0000);
+        write_sprite(&mut gpu, 0, 16, 8, 1, 0);
+        write_sprite(&mut gpu, 1, 16, 16, 1, SPRITE_X_FLIP);
+        write_sprite(&mut gpu, 2, 16, 24, 1, SPRITE_Y_FLIP);
+        write_sprite(&mut gpu, 3, 16, 32, 1, SPRITE_X_FLIP | SPRITE_Y_FLIP);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0011);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 3);
+        assert_eq!(pixel(&gpu, 15, 0), 3);
+        assert_eq!(pixel(&gpu, 8, 0), 0);
+        assert_eq!(pixel(&gpu, 16, 7), 3);
+        assert_eq!(pixel(&gpu, 16, 0), 0);
+        assert_eq!(pixel(&gpu, 31, 7), 3);
+    }
+
+    #[test]
+    fn ten_sprites_per_line() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        for index in 0..12 {
+            write_sprite(&mut gpu, index, 16, 8 + index as u8 * 8, 1, 0);
+        }
+        // Off this line entirely, doesn't use up a slot
+        write_sprite(&mut gpu, 12, 100, 8, 1, 0);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0011);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 72, 0), 3);
+        assert_eq!(pixel(&gpu, 80, 0), 0);
+        assert_eq!(pixel(&gpu, 88, 0), 0);
+    }
+
+    #[test]
+    fn lower_x_wins_then_oam_order() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8010, 1);
+        write_solid_tile(&mut gpu, 0x8020, 2);
+        // Later in OAM but further left
+        write_sprite(&mut gpu, 0, 16, 12, 1, 0);
+        write_sprite(&mut gpu, 1, 16, 8, 2, 0);
+        // Same X, first one in OAM wins
+        write_sprite(&mut gpu, 2, 40, 8, 1, 0);
+        write_sprite(&mut gpu, 3, 40, 8, 2, 0);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0011);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 5, 0), 2);
+        assert_eq!(pixel(&gpu, 9, 0), 1);
+        assert_eq!(pixel(&gpu, 5, 24), 1);
+    }
+
+    #[test]
+    fn bg_over_obj_priority() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8010, 3);
+        write_solid_tile(&mut gpu, 0x8020, 2);
+        // bg tile in the second column only
+        gpu.write_byte(0x9801, 0x02);
+        write_sprite(&mut gpu, 0, 16, 12, 1, SPRITE_BG_PRIORITY);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0011);
+
+        render_frame(&mut gpu);
+
+        // Shows over bg colour 0, hides behind anything else
+        assert_eq!(pixel(&gpu, 4, 0), 3);
+        assert_eq!(pixel(&gpu, 8, 0), 2);
+    }
+
+    #[test]
+    fn tall_sprites_ignore_tile_bit_0() {
+        let mut gpu = sprite_gpu();
+        write_solid_tile(&mut gpu, 0x8020, 1);
+        write_solid_tile(&mut gpu, 0x8030, 2);
+        write_sprite(&mut gpu, 0, 16, 8, 3, 0);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0111);
+
+        render_frame(&mut gpu);
+
+        assert_eq!(pixel(&gpu, 0, 0), 1);
+        assert_eq!(pixel(&gpu, 0, 7), 1);
+        assert_eq!(pixel(&gpu, 0, 8), 2);
+        assert_eq!(pixel(&gpu, 0, 15), 2);
+        assert_eq!(pixel(&gpu, 0, 16), 0);
+    }
+
+    // Counts STAT requests over a number of dots, stepping 4 at a time like the cpu would
+    fn count_stat_interrupts(gpu: &mut Gpu, dots: u32) -> u32 {
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+        let mut count = 0;
+
+        for _ in 0..dots / 4 {
+            gpu.step(4, &mut interrupts);
+            if interrupts.pending() & Interrupt::LcdStat.mask() > 0 {
+                interrupts.clear(Interrupt::LcdStat);
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    #[test]
+    fn vblank_requested_at_line_144() {
+        let mut gpu = Gpu::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+
+        // Line 143 hblank ends 4 dots early
+        gpu.step(144 * 456 - 4, &mut interrupts);
+        assert_eq!(interrupts.pending(), 0);
+
+        gpu.step(4, &mut interrupts);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 144);
+        assert_eq!(interrupts.pending(), Interrupt::VBlank.mask());
+    }
+
+    #[test]
+    fn stat_on_lyc_match() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(LCD_Y_COMPARE_ADDRESS, 3);
+        gpu.write_byte(LCD_STATUS_ADDRESS, 0b0100_0000);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+
+        assert_eq!(count_stat_interrupts(&mut gpu, 3 * 456 - 4), 0);
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS) & 0b0100, 0);
+
+        assert_eq!(count_stat_interrupts(&mut gpu, 4), 1);
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS) & 0b0100, 0b0100);
+
+        // Once per frame
+        assert_eq!(count_stat_interrupts(&mut gpu, 154 * 456), 1);
+    }
+
+    #[test]
+    fn stat_line_blocks_back_to_back_sources() {
+        let mut gpu = Gpu::new();
+        // hblank and LYC=1, line 0 hblank runs straight into the line 1 match
+        gpu.write_byte(LCD_Y_COMPARE_ADDRESS, 1);
+        gpu.write_byte(LCD_STATUS_ADDRESS, 0b0100_1000);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+
+        // Line 0 hblank, (line 1 match and hblank blocked), line 2 hblank
+        assert_eq!(count_stat_interrupts(&mut gpu, 3 * 456), 2);
+    }
+
+    #[test]
+    fn stat_mode_and_coincidence_are_read_only() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(LCD_Y_COMPARE_ADDRESS, 5);
+        gpu.write_byte(LCD_STATUS_ADDRESS, 0b0111_1111);
+
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS), 0b1111_1000);
+    }
+
+    // A bit of everything: scrolled bg, window, sprites with flips, priority and left edge clipping
+    fn busy_scene(renderer: Renderer) -> Gpu {
+        let mut gpu = sprite_gpu();
+        gpu.set_renderer(renderer);
+
+        for tile in 0..4u16 {
+            for row in 0..8 {
+                let address = 0x8000 + tile * 16 + row * 2;
+                gpu.write_byte(address, 0x3C ^ (tile * 0x11 + row) as u8);
+                gpu.write_byte(address + 1, 0x5A + (tile * 7 + row) as u8);
+            }
+        }
+        for i in 0..0x800u16 {
+            gpu.write_byte(0x9800 + i, (i % 3) as u8 + (i / 33 % 2) as u8);
+        }
+
+        gpu.write_byte(BACKGROUND_X_ADDRESS, 13);
+        gpu.write_byte(BACKGROUND_Y_ADDRESS, 250);
+        gpu.write_byte(WINDOW_Y_ADDRESS, 90);
+        gpu.write_byte(WINDOW_X_ADDRESS, 100);
+
+        write_sprite(&mut gpu, 0, 20, 4, 1, 0);
+        write_sprite(&mut gpu, 1, 24, 8, 2, SPRITE_X_FLIP);
+        write_sprite(&mut gpu, 2, 24, 10, 3, SPRITE_Y_FLIP | SPRITE_PALETTE);
+        write_sprite(&mut gpu, 3, 60, 80, 2, SPRITE_BG_PRIORITY);
+        write_sprite(&mut gpu, 4, 100, 104, 1, 0);
+        write_sprite(&mut gpu, 5, 100, 104, 3, SPRITE_X_FLIP);
+
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1111_0011);
+        render_frame(&mut gpu);
+        gpu
+    }
+
+    // Dots spent in mode 3 on line 0
+    fn mode_3_length(gpu: &mut Gpu) -> u32 {
+        let mut interrupts = Interrupts::new();
+        let lcd_control = gpu.read_byte(LCD_CONTROL_ADDRESS);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x00);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, lcd_control | 0x80);
+
+        let mut dots = 0;
+        for _ in 0..456 {
+            gpu.step(1, &mut interrupts);
+            if gpu.read_byte(LCD_STATUS_ADDRESS) & 0b11 == Mode::Draw as u8 {
+                dots += 1;
+            }
+        }
+        dots
+    }
+
+    #[test]
+    fn pixel_fifo_matches_scanline_renderer() {
+        let scanline = busy_scene(Renderer::Scanline);
+        let pixel_fifo = busy_scene(Renderer::PixelFifo);
+
+        assert!(scanline.framebuffer().iter().any(|shade| *shade != 0));
+        assert!(scanline.framebuffer() == pixel_fifo.framebuffer());
+    }
+
+    #[test]
+    fn pixel_fifo_frame_timing() {
+        let mut gpu = Gpu::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+        gpu.set_renderer(Renderer::PixelFifo);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0x80);
+
+        gpu.step(144 * 456 - 1, &mut interrupts);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 143);
+        assert_eq!(interrupts.pending(), 0);
+
+        gpu.step(1, &mut interrupts);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 144);
+        assert_eq!(interrupts.pending(), Interrupt::VBlank.mask());
+
+        gpu.step(10 * 456, &mut interrupts);
+        assert_eq!(gpu.read_byte(LCD_Y_ADDRESS), 0);
+        assert_eq!(gpu.read_byte(LCD_STATUS_ADDRESS) & 0b11, Mode::Oam as u8);
+    }
+
+    #[test]
+    fn pixel_fifo_mode_3_length() {
+        let mut gpu = Gpu::new();
+        gpu.set_renderer(Renderer::PixelFifo);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b0000_0011);
+        assert_eq!(mode_3_length(&mut gpu), 172);
+
+        // Fine scroll
+        gpu.write_byte(BACKGROUND_X_ADDRESS, 3);
+        assert_eq!(mode_3_length(&mut gpu), 175);
+        gpu.write_byte(BACKGROUND_X_ADDRESS, 0);
+
+        // Window restarting the fetcher partway along
+        gpu.write_byte(WINDOW_X_ADDRESS, 87);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b0010_0011);
+        assert_eq!(mode_3_length(&mut gpu), 178);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b0000_0011);
+
+        // A sprite stalls for its own fetch plus whatever is left of the bg fetch, worst at
+        // the start of a tile and nothing extra at the end of one
+        write_sprite(&mut gpu, 0, 16, 88, 0, 0);
+        assert_eq!(mode_3_length(&mut gpu), 184);
+        write_sprite(&mut gpu, 0, 16, 95, 0, 0);
+        assert_eq!(mode_3_length(&mut gpu), 178);
+
+        // The scanline renderer doesn't care
+        gpu.set_renderer(Renderer::Scanline);
+        assert_eq!(mode_3_length(&mut gpu), 172);
+    }
+
+    #[test]
+    fn pixel_fifo_mid_line_palette_change() {
+        let mut gpu = Gpu::new();
+        let mut interrupts = Interrupts::new();
+        gpu.set_renderer(Renderer::PixelFifo);
+        write_solid_tile(&mut gpu, 0x8000, 1);
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b0000_0100);
+        gpu.write_byte(LCD_CONTROL_ADDRESS, 0b1001_0001);
+
+        // Pixel x comes out on dot 93 + x
+        gpu.step(92 + 80, &mut interrupts);
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0b0000_1100);
+        gpu.step(456 - 92 - 80, &mut interrupts);
+
+        assert_eq!(pixel(&gpu, 79, 0), 1);
+        assert_eq!(pixel(&gpu, 80, 0), 3);
+        assert_eq!(pixel(&gpu, 159, 0), 3);
+    }
+
+    #[test]
+    fn palettes_read_back() {
+        let mut gpu = Gpu::new();
+        gpu.write_byte(PALETTE_BG_ADDRESS, 0xE4);
+        gpu.write_byte(PALETTE_OBJ_0_ADDRESS, 0xD2);
+        gpu.write_byte(PALETTE_OBJ_1_ADDRESS, 0x1B);
+
+        assert_eq!(gpu.read_byte(PALETTE_BG_ADDRESS), 0xE4);
+        assert_eq!(gpu.read_byte(PALETTE_OBJ_0_ADDRESS), 0xD2);
+        assert_eq!(gpu.read_byte(PALETTE_OBJ_1_ADDRESS), 0x1B);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+
+use super::{
+    Gpu, Mode, NUM_CYCLES_OAM, SCREEN_WIDTH, SPRITE_BG_PRIORITY, SPRITE_X_FLIP, Sprite,
+    apply_palette, tile_row_color_id,
+};
+use crate::interrupts::Interrupts;
+
+const DOTS_PER_LINE: u32 = 456;
+// The first tile fetch of every line gets thrown away
+const WARMUP_DOTS: u8 = 6;
+// Tile number, low byte and high byte each take 2 dots
+const DOTS_PER_FETCH_STEP: u8 = 2;
+const DOTS_PER_SPRITE_FETCH: u8 = 6;
+
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+enum FetchStep {
+    #[default]
+    TileNumber,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+// Background/window fetcher, produces 8 pixels at a time
+#[derive(Default)]
+struct Fetcher {
+    step: FetchStep,
+    dots: u8,
+    tile_x: u8,
+    tile_index: u8,
+    low: u8,
+    high: u8,
+    in_window: bool,
+}
+
+impl Fetcher {
+    // Sitting on a finished fetch, waiting for the fifo to empty
+    fn stalled(&self, background: &VecDeque<u8>) -> bool {
+        self.step == FetchStep::Push && !background.is_empty()
+    }
+}
+
+#[derive(Copy, Clone)]
+struct SpritePixel {
+    color_id: u8,
+    attributes: u8,
+}
+
+struct SpriteFetch {
+    sprite: Sprite,
+    dots: u8,
+}
+
+// Dot by dot mode 3 (https://gbdev.io/pandocs/pixel_fifo.html)
+// Mode 3 is 172 dots with nothing going on, plus SCX % 8 for the fine scroll, 6 for the window
+// restarting the fetcher, and 6-12 for each sprite depending on how far along the background
+// fetch is. Palettes and the priority checks are applied as pixels leave the fifo.
+#[derive(Default)]
+pub(super) struct PixelFifo {
+    line_dots: u32,
+    background: VecDeque<u8>,
+    sprite_pixels: VecDeque<SpritePixel>,
+    fetcher: Fetcher,
+    sprite_fetch: Option<SpriteFetch>,
+    // From the oam scan, taken out as they get fetched
+    sprites: Vec<Sprite>,
+    // Next pixel on the line
+    x: usize,
+    // SCX % 8 pixels get popped and dropped before anything is drawn
+    discard: u8,
+    warmup: u8,
+    window_used: bool,
+}
+
+impl PixelFifo {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    fn tick(&mut self, gpu: &mut Gpu, interrupts: &mut Interrupts) {
+        self.line_dots += 1;
+
+        match gpu.lcd_status.mode {
+            Mode::Oam => {
+                if self.line_dots >= NUM_CYCLES_OAM {
+                    self.start_line(gpu);
+                    gpu.lcd_status.mode = Mode::Draw;
+                    gpu.update_stat_line(interrupts);
+                }
+            }
+            Mode::Draw => {
+                self.tick_draw(gpu);
+
+                if self.x == SCREEN_WIDTH {
+                    if self.window_used {
+                        gpu.window_line += 1;
+                    }
+                    gpu.lcd_status.mode = Mode::Hblank;
+                    gpu.update_stat_line(interrupts);
+                }
+            }
+            Mode::Hblank | Mode::Vblank => {
+                if self.line_dots >= DOTS_PER_LINE {
+                    self.line_dots = 0;
+                    gpu.next_line(interrupts);
+                    gpu.update_stat_line(interrupts);
+                }
+            }
+        }
+    }
+
+    fn start_line(&mut self, gpu: &Gpu) {
+        self.background.clear();
+        self.sprite_pixels.clear();
+        self.fetcher = Fetcher::default();
+        self.sprite_fetch = None;
+        self.sprites = gpu.scan_oam(gpu.lcd_y);
+        self.x = 0;
+        self.discard = gpu.background_x % 8;
+        self.warmup = WARMUP_DOTS;
+        self.window_used = false;
+    }
+
+    fn tick_draw(&mut self, gpu: &mut Gpu) {
+        if self.warmup > 0 {
+            self.warmup -= 1;
+            return;
+        }
+
+        if self.sprite_fetch.is_none() && self.discard == 0 && gpu.lcd_control.sprites_enabled {
+            self.sprite_fetch = self
+                .next_sprite()
+                .map(|sprite| SpriteFetch { sprite, dots: 0 });
+        }
+
+        // Pixel output stops while a sprite is fetched. The bg fetcher has to have a tile
+        // fetched and waiting first, which costs the most right at the start of a tile.
+        if let Some(sprite_fetch) = &mut self.sprite_fetch {
+            if !self.fetcher.stalled(&self.background) {
+                tick_fetcher(&mut self.fetcher, &mut self.background, gpu);
+                if !self.fetcher.stalled(&self.background) {
+                    return;
+                }
+            }
+
+            sprite_fetch.dots += 1;
+            if sprite_fetch.dots == DOTS_PER_SPRITE_FETCH {
+                let sprite = sprite_fetch.sprite;
+                self.sprite_fetch = None;
+                self.merge_sprite(gpu, &sprite);
+            }
+            return;
+        }
+
+        let line = gpu.lcd_y;
+        if !self.fetcher.in_window
+            && self.discard == 0
+            && gpu.window_visible_on(line)
+            && self.x as u16 + 7 >= gpu.window_x as u16
+        {
+            // Window starts over with an empty fifo and a fresh fetch
+            self.background.clear();
+            self.fetcher = Fetcher {
+                in_window: true,
+                ..Fetcher::default()
+            };
+            self.window_used = true;
+        }
+
+        tick_fetcher(&mut self.fetcher, &mut self.background, gpu);
+
+        let Some(color_id) = self.background.pop_front() else {
+            return;
+        };
+        let sprite_pixel = self.sprite_pixels.pop_front();
+
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
+        }
+
+        let shade = match sprite_pixel {
+            Some(pixel)
+                if pixel.color_id != 0
+                    && gpu.lcd_control.sprites_enabled
+                    && (pixel.attributes & SPRITE_BG_PRIORITY == 0 || color_id == 0) =>
+            {
+                apply_palette(gpu.sprite_palette(pixel.attributes), pixel.color_id)
+            }
+            _ => apply_palette(gpu.palette_bg, color_id),
+        };
+
+        gpu.framebuffer[line as usize * SCREEN_WIDTH + self.x] = shade;
+        self.x += 1;
+    }
+
+    // Smallest X that has been reached, OAM order for ties (min_by_key keeps the first)
+    fn next_sprite(&mut self) -> Option<Sprite> {
+        let x = self.x as i16;
+        let (index, _) = self
+            .sprites
+            .iter()
+            .enumerate()
+            .filter(|(_, sprite)| sprite.x <= x)
+            .min_by_key(|(_, sprite)| sprite.x)?;
+
+        Some(self.sprites.remove(index))
+    }
+
+    // Sprites already in the fifo got there first and so have priority, only their
+    // transparent pixels get filled in
+    fn merge_sprite(&mut self, gpu: &Gpu, sprite: &Sprite) {
+        let (low, high) = gpu.sprite_tile_row(sprite, gpu.lcd_y);
+
+        // Anything left of the current pixel (off the left edge) is already gone
+        let skipped = (self.x as i16 - sprite.x).max(0) as u8;
+
+        for column in skipped..8 {
+            let tile_column = if sprite.attributes & SPRITE_X_FLIP > 0 {
+                7 - column
+            } else {
+                column
+            };
+            let pixel = SpritePixel {
+                color_id: tile_row_color_id(low, high, tile_column),
+                attributes: sprite.attributes,
+            };
+
+            let slot = (column - skipped) as usize;
+            match self.sprite_pixels.get_mut(slot) {
+                Some(existing) if existing.color_id == 0 => *existing = pixel,
+                Some(_) => {}
+                None => self.sprite_pixels.push_back(pixel),
+            }
+        }
+    }
+}
+
+fn tick_fetcher(fetcher: &mut Fetcher, background: &mut VecDeque<u8>, gpu: &Gpu) {
+    if fetcher.step == FetchStep::Push {
+        // Only pushes into an empty fifo
+        if background.is_empty() {
+            for x in 0..8 {
+                let color_id = if gpu.lcd_control.background_and_window_enabled {
+                    tile_row_color_id(fetcher.low, fetcher.high, x)
+                } else {
+                    0
+                };
+                background.push_back(color_id);
+            }
+
+            fetcher.tile_x = fetcher.tile_x.wrapping_add(1);
+            fetcher.step = FetchStep::TileNumber;
+        }
+        return;
+    }
+
+    fetcher.dots += 1;
+    if fetcher.dots < DOTS_PER_FETCH_STEP {
+        return;
+    }
+    fetcher.dots = 0;
+
+    // Scroll and the map/data selects are read live, so mid-line writes land where they happen
+    let line = gpu.lcd_y;
+    let (map_base, map_x, map_y) = if fetcher.in_window {
+        (gpu.window_map(), fetcher.tile_x, gpu.window_line)
+    } else {
+        (
+            gpu.background_map(),
+            (gpu.background_x / 8).wrapping_add(fetcher.tile_x) & 31,
+            line.wrapping_add(gpu.background_y),
+        )
+    };
+
+    match fetcher.step {
+        FetchStep::TileNumber => {
+            let map_address = map_base + (map_y as u16 / 8) * 32 + (map_x as u16 & 31);
+            fetcher.tile_index = gpu.read_vram(map_address);
+            fetcher.step = FetchStep::DataLow;
+        }
+        FetchStep::DataLow => {
+            let row_address = gpu.tile_data_address(fetcher.tile_index) + (map_y as u16 % 8) * 2;
+            fetcher.low = gpu.read_vram(row_address);
+            fetcher.step = FetchStep::DataHigh;
+        }
+        FetchStep::DataHigh => {
+            let row_address = gpu.tile_data_address(fetcher.tile_index) + (map_y as u16 % 8) * 2;
+            fetcher.high = gpu.read_vram(row_address + 1);
+            fetcher.step = FetchStep::Push;
+        }
+        FetchStep::Push => {}
+    }
+}
+
+impl Gpu {
+    pub(super) fn step_pixel_fifo(&mut self, t_cycles: u32, interrupts: &mut Interrupts) {
+        // Taken out for the duration so the fifo and the rest of the gpu can be borrowed apart
+        let mut pixel_fifo = std::mem::take(&mut self.pixel_fifo);
+        for _ in 0..t_cycles {
+            pixel_fifo.tick(self, interrupts);
+        }
+        self.pixel_fifo = pixel_fifo;
+    }
+}
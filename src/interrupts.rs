@@ -0,0 +1,149 @@
+const INTERRUPT_BITS: u8 = 0b0001_1111;
+
+// Ordered by priority, VBlank wins when several are pending at once
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank = 0,
+    LcdStat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+impl Interrupt {
+    const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    // 0x40, 0x48, 0x50, 0x58, 0x60
+    pub fn vector(self) -> u16 {
+        0x0040 + 0x0008 * (self as u16)
+    }
+}
+
+// IE (0xFFFF) and IF (0xFF0F). IME lives with the registers since only the cpu touches it.
+pub struct Interrupts {
+    enabled: u8,
+    flags: u8,
+}
+
+impl Interrupts {
+    pub fn new() -> Self {
+        Self {
+            enabled: 0x00,
+            flags: 0x00,
+        }
+    }
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flags |= interrupt.mask();
+    }
+
+    pub fn clear(&mut self, interrupt: Interrupt) {
+        self.flags &= !interrupt.mask();
+    }
+
+    // IE & IF, regardless of IME. HALT wakes up on this even with interrupts disabled.
+    pub fn pending(&self) -> u8 {
+        self.enabled & self.flags & INTERRUPT_BITS
+    }
+
+    pub fn highest_priority_pending(&self) -> Option<Interrupt> {
+        let pending = self.pending();
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() > 0)
+    }
+
+    // Upper 3 bits of IF aren't wired and always read back as 1
+    pub fn read_flags(&self) -> u8 {
+        self.flags | !INTERRUPT_BITS
+    }
+
+    pub fn write_flags(&mut self, value: u8) {
+        self.flags = value & INTERRUPT_BITS;
+    }
+
+    // All 8 bits of IE are plain storage, only the lower 5 do anything
+    pub fn read_enabled(&self) -> u8 {
+        self.enabled
+    }
+
+    pub fn write_enabled(&mut self, value: u8) {
+        self.enabled = value;
+    }
+}
+
+impl Default for Interrupts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_needs_both_enabled_and_requested() {
+        let mut interrupts = Interrupts::new();
+
+        interrupts.request(Interrupt::Timer);
+        assert_eq!(interrupts.pending(), 0);
+
+        interrupts.write_enabled(Interrupt::Timer.mask());
+        assert_eq!(interrupts.pending(), 0b0000_0100);
+        assert_eq!(
+            interrupts.highest_priority_pending(),
+            Some(Interrupt::Timer)
+        );
+    }
+
+    #[test]
+    fn vblank_has_highest_priority() {
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+
+        interrupts.request(Interrupt::Joypad);
+        interrupts.request(Interrupt::VBlank);
+        interrupts.request(Interrupt::Serial);
+
+        assert_eq!(
+            interrupts.highest_priority_pending(),
+            Some(Interrupt::VBlank)
+        );
+        interrupts.clear(Interrupt::VBlank);
+        assert_eq!(
+            interrupts.highest_priority_pending(),
+            Some(Interrupt::Serial)
+        );
+    }
+
+    #[test]
+    fn flags_upper_bits_read_as_set() {
+        let mut interrupts = Interrupts::new();
+
+        interrupts.write_flags(0xFF);
+        assert_eq!(interrupts.read_flags(), 0xFF);
+
+        interrupts.write_flags(0x00);
+        assert_eq!(interrupts.read_flags(), 0xE0);
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(Interrupt::VBlank.vector(), 0x0040);
+        assert_eq!(Interrupt::LcdStat.vector(), 0x0048);
+        assert_eq!(Interrupt::Timer.vector(), 0x0050);
+        assert_eq!(Interrupt::Serial.vector(), 0x0058);
+        assert_eq!(Interrupt::Joypad.vector(), 0x0060);
+    }
+}
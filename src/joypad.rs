@@ -0,0 +1,171 @@
+use crate::interrupts::{Interrupt, Interrupts};
+
+pub const JOYPAD_ADDRESS: u16 = 0xFF00;
+
+const SELECT_DPAD: u8 = 0b0001_0000;
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+const SELECT_BITS: u8 = SELECT_DPAD | SELECT_BUTTONS;
+const LINE_BITS: u8 = 0b0000_1111;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // Low nibble is the d-pad, high nibble the action buttons, both in P1 line order
+    fn mask(self) -> u8 {
+        match self {
+            Button::Right => 0b0000_0001,
+            Button::Left => 0b0000_0010,
+            Button::Up => 0b0000_0100,
+            Button::Down => 0b0000_1000,
+            Button::A => 0b0001_0000,
+            Button::B => 0b0010_0000,
+            Button::Select => 0b0100_0000,
+            Button::Start => 0b1000_0000,
+        }
+    }
+}
+
+// P1/JOYP (https://gbdev.io/pandocs/Joypad_Input.html)
+// Everything is active low: writing 0 to bit 4/5 selects a row, a pressed button pulls its line to 0.
+pub struct Joypad {
+    select: u8,
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self {
+            select: 0x00,
+            pressed: 0x00,
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool, interrupts: &mut Interrupts) {
+        let before = self.lines();
+
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+
+        self.check_interrupt(before, interrupts);
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    // P10-P13 as the cpu sees them, 0 means pressed
+    pub fn lines(&self) -> u8 {
+        let mut low = 0x00;
+
+        if self.select & SELECT_DPAD == 0 {
+            low |= self.pressed & LINE_BITS;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            low |= self.pressed >> 4;
+        }
+
+        !low & LINE_BITS
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        0b1100_0000 | self.select | self.lines()
+    }
+
+    // Only the select bits are writable
+    pub fn write_byte(&mut self, value: u8, interrupts: &mut Interrupts) {
+        let before = self.lines();
+        self.select = value & SELECT_BITS;
+        self.check_interrupt(before, interrupts);
+    }
+
+    // Fires on any line going high -> low
+    fn check_interrupt(&self, before: u8, interrupts: &mut Interrupts) {
+        if before & !self.lines() != 0 {
+            interrupts.request(Interrupt::Joypad);
+        }
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_selected_row() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+
+        joypad.set_button(Button::Start, true, &mut interrupts);
+        joypad.set_button(Button::Left, true, &mut interrupts);
+
+        // d-pad row
+        joypad.write_byte(0b0010_0000, &mut interrupts);
+        assert_eq!(joypad.read_byte(), 0b1110_1101);
+
+        // Button row
+        joypad.write_byte(0b0001_0000, &mut interrupts);
+        assert_eq!(joypad.read_byte(), 0b1101_0111);
+
+        // Nothing selected
+        joypad.write_byte(0b0011_0000, &mut interrupts);
+        assert_eq!(joypad.read_byte(), 0b1111_1111);
+    }
+
+    #[test]
+    fn release_clears_line() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+        joypad.write_byte(0b0001_0000, &mut interrupts);
+
+        joypad.set_button(Button::A, true, &mut interrupts);
+        assert!(joypad.is_pressed(Button::A));
+        assert_eq!(joypad.lines(), 0b1110);
+
+        joypad.set_button(Button::A, false, &mut interrupts);
+        assert!(!joypad.is_pressed(Button::A));
+        assert_eq!(joypad.lines(), 0b1111);
+    }
+
+    #[test]
+    fn interrupt_on_high_to_low() {
+        let mut joypad = Joypad::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+        joypad.write_byte(0b0001_0000, &mut interrupts);
+
+        // Not selected, line doesn't move
+        joypad.set_button(Button::Down, true, &mut interrupts);
+        assert_eq!(interrupts.pending(), 0);
+
+        joypad.set_button(Button::B, true, &mut interrupts);
+        assert_eq!(interrupts.pending(), Interrupt::Joypad.mask());
+
+        // Releasing is low -> high
+        interrupts.clear(Interrupt::Joypad);
+        joypad.set_button(Button::B, false, &mut interrupts);
+        assert_eq!(interrupts.pending(), 0);
+
+        // Selecting a row with a button already held pulls the line down too
+        joypad.write_byte(0b0010_0000, &mut interrupts);
+        assert_eq!(interrupts.pending(), Interrupt::Joypad.mask());
+    }
+}
@@ -0,0 +1,22 @@
+pub mod apu;
+pub mod cartridge;
+pub mod clock;
+pub mod cpu;
+pub mod cpu_logic;
+pub mod debugger;
+pub mod disasm;
+pub mod gpu;
+pub mod interrupts;
+pub mod joypad;
+pub mod mbc;
+pub mod memory;
+pub mod motherboard;
+pub mod opcode;
+mod opcode_tests;
+pub mod registers;
+pub mod runner;
+pub mod screenshot;
+pub mod serial;
+pub mod symbols;
+pub mod timer;
+pub mod wav;
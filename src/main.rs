@@ -1,41 +1,312 @@
-use crate::cartridge::Cartridge;
-use crate::registers::{RegByte, Registers};
-
-mod cartridge;
-mod clock;
-mod cpu;
-mod cpu_logic;
-mod gpu;
-mod memory;
-mod motherboard;
-mod opcode;
-mod opcode_tests;
-mod registers;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+use emoboy::debugger::{self, Debugger};
+use emoboy::disasm;
+use emoboy::motherboard::Motherboard;
+use emoboy::runner::{self, RunLimits, StopReason};
+use emoboy::screenshot;
+use emoboy::symbols::Symbols;
+
+// Exit codes, for CI to assert on
+const EXIT_OK: i32 = 0;
+// Breakpoints were given but the run hit its limit first
+const EXIT_BREAKPOINT_MISSED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+// Couldn't load a rom or write an output file
+const EXIT_IO: i32 = 3;
+
+// The Gameboy Doctor reference logs were made with LY stuck at the start of vblank
+const DOCTOR_LY: u8 = 0x90;
+
+// Used when neither --frames nor --cycles is given, breakpoints or not, so a missed
+// breakpoint still ends the run. One second.
+const DEFAULT_FRAMES: u64 = 60;
+
+const USAGE: &str = "\
+Usage: emoboy <rom> [options]
+       emoboy disasm <rom> [--symbols <file>]
+
+Runs the rom headlessly, then prints the registers. With --debug it starts a debugger
+prompt instead, type help there for the commands. disasm prints the whole rom as RGBDS
+assembly, one section per bank.
+
+Options:
+  --frames <n>          Stop after n frames (60 unless --cycles is given)
+  --cycles <n>          Stop after n m-cycles
+  --break <address>     Stop before executing the instruction at a hex address or label,
+                        repeatable
+  --screenshot <file>   Save the screen at the end, .ppm for PPM, PNG otherwise
+  --boot-rom <file>     Run a 256 byte DMG boot rom first instead of skipping it
+  --record-audio <file> Record everything played to a 16-bit stereo WAV
+  --trace <file>        Log every instruction in Gameboy Doctor format
+  --doctor-ly           Make LY always read 90, like the Gameboy Doctor reference logs
+  --debug               Step through the rom interactively, --break sets initial breakpoints
+  --symbols <file>      RGBDS .sym file for labels, <rom name>.sym is picked up by default
+
+Exit codes: 0 finished, 1 a breakpoint was never reached, 2 bad arguments, 3 file error";
+
+struct Options {
+    rom_path: String,
+    boot_rom: Option<String>,
+    symbols: Option<String>,
+    // Resolved into limits once the symbols are loaded
+    breakpoints: Vec<String>,
+    limits: RunLimits,
+    screenshot: Option<String>,
+    record_audio: Option<String>,
+    trace: Option<String>,
+    doctor_ly: bool,
+    debug: bool,
+}
 
 fn main() {
-    println!("Hello, world!");
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "disasm") {
+        let (rom_path, symbols) = parse_disasm_args(args.skip(1)).unwrap_or_else(|message| {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(EXIT_USAGE);
+        });
+        process::exit(disassemble(&rom_path, symbols.as_deref()));
+    }
 
-    // get a cartridge and pass it into the motherboard constructor
+    let options = parse_args(args).unwrap_or_else(|message| {
+        eprintln!("{}\n\n{}", message, USAGE);
+        process::exit(EXIT_USAGE);
+    });
+
+    process::exit(run(options));
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut options = Options {
+        rom_path: String::new(),
+        boot_rom: None,
+        symbols: None,
+        breakpoints: Vec::new(),
+        limits: RunLimits {
+            frames: None,
+            m_cycles: None,
+            breakpoints: Vec::new(),
+        },
+        screenshot: None,
+        record_audio: None,
+        trace: None,
+        doctor_ly: false,
+        debug: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} expects a value", arg));
+
+        match arg.as_str() {
+            "--frames" => options.limits.frames = Some(parse_number(&value()?)?),
+            "--cycles" => options.limits.m_cycles = Some(parse_number(&value()?)?),
+            "--break" => options.breakpoints.push(value()?),
+            "--screenshot" => options.screenshot = Some(value()?),
+            "--boot-rom" => options.boot_rom = Some(value()?),
+            "--record-audio" => options.record_audio = Some(value()?),
+            "--symbols" => options.symbols = Some(value()?),
+            "--trace" => options.trace = Some(value()?),
+            "--doctor-ly" => options.doctor_ly = true,
+            "--debug" => options.debug = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(EXIT_OK);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown flag {}", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    options.rom_path = rom_path.ok_or("No rom given")?;
+
+    let limits = &mut options.limits;
+    if limits.frames.is_none() && limits.m_cycles.is_none() {
+        limits.frames = Some(DEFAULT_FRAMES);
+    }
+
+    Ok(options)
+}
 
-    let mut motherboard = motherboard::Motherboard::new();
+fn parse_disasm_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(String, Option<String>), String> {
+    let mut rom_path = None;
+    let mut symbols = None;
 
-    // TODO this feels wrong, why does motherboard load a rom. might need to add a motherboard/device type struct eventually
-    motherboard.load_rom_file("assets/andy_test_rom.bin");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symbols" => symbols = Some(args.next().ok_or("--symbols expects a value")?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown flag {}", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
 
-    // Experimental/testing purposes
-    let mut cart = Cartridge::new();
-    cart.load_rom_file("assets/andy_test_rom.bin");
+    Ok((rom_path.ok_or("disasm expects a rom")?, symbols))
+}
+
+// --symbols if given, otherwise <rom name>.sym if there is one
+fn symbols_path(rom_path: &str, symbols: Option<&str>) -> Option<String> {
+    match symbols {
+        Some(symbols) => Some(symbols.to_string()),
+        None => {
+            let path = Path::new(rom_path).with_extension("sym");
+            path.exists().then(|| path.to_string_lossy().into_owned())
+        }
+    }
+}
+
+fn parse_number(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Expected a number, got {}", value))
+}
+
+fn run(mut options: Options) -> i32 {
+    let mut motherboard = Motherboard::new();
+
+    // Has to go in before the cartridge, loading that skips straight to the post-boot state otherwise
+    if let Some(boot_rom) = &options.boot_rom
+        && let Err(error) = motherboard.load_boot_rom_file(boot_rom)
+    {
+        eprintln!("Couldn't load boot rom {}: {}", boot_rom, error);
+        return EXIT_IO;
+    }
+    match motherboard.load_rom_file(&options.rom_path) {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => eprintln!("Couldn't write save file: {}", error),
+        Err(error) => {
+            eprintln!("Couldn't load rom {}: {}", options.rom_path, error);
+            return EXIT_IO;
+        }
+    }
+
+    if let Some(symbols_path) = symbols_path(&options.rom_path, options.symbols.as_deref())
+        && let Err(error) = motherboard.load_symbol_file(&symbols_path)
+    {
+        eprintln!("Couldn't load symbols {}: {}", symbols_path, error);
+        return EXIT_IO;
+    }
+    for breakpoint in &options.breakpoints {
+        match debugger::resolve_breakpoint(&motherboard.symbols, breakpoint) {
+            Ok(breakpoint) => options.limits.breakpoints.push(breakpoint),
+            Err(message) => {
+                eprintln!("{}", message);
+                return EXIT_USAGE;
+            }
+        }
+    }
+
+    if let Some(wav_path) = &options.record_audio
+        && let Err(error) = motherboard.start_audio_recording(wav_path)
+    {
+        eprintln!("Couldn't record to {}: {}", wav_path, error);
+        return EXIT_IO;
+    }
+    if options.doctor_ly {
+        motherboard.memory.gpu.set_fixed_lcd_y(Some(DOCTOR_LY));
+    }
+    if let Some(trace_path) = &options.trace
+        && let Err(error) = motherboard.start_trace(trace_path)
+    {
+        eprintln!("Couldn't trace to {}: {}", trace_path, error);
+        return EXIT_IO;
+    }
+
+    if options.debug {
+        return debug(&mut motherboard, &options);
+    }
+
+    let stop_reason = runner::run(&mut motherboard, &options.limits);
+
+    match stop_reason {
+        StopReason::Breakpoint(address) => match motherboard.label_at(address) {
+            Some(name) => println!("Breakpoint at {:04X} ({})", address, name),
+            None => println!("Breakpoint at {:04X}", address),
+        },
+        StopReason::FrameLimit(frames) => println!("Stopped after {} frames", frames),
+        StopReason::CycleLimit => {
+            println!("Stopped after {} m-cycles", motherboard.clock.m_cycles())
+        }
+    }
+    println!("{}", motherboard.registers.dump());
+
+    if let Err(error) = motherboard.stop_audio_recording() {
+        eprintln!("Couldn't finish recording: {}", error);
+        return EXIT_IO;
+    }
+    if let Err(error) = motherboard.stop_trace() {
+        eprintln!("Couldn't finish trace: {}", error);
+        return EXIT_IO;
+    }
+    if let Err(error) = motherboard.shutdown() {
+        eprintln!("Couldn't write save file: {}", error);
+        return EXIT_IO;
+    }
+    if let Some(screenshot_path) = &options.screenshot
+        && let Err(error) = screenshot::save(screenshot_path, motherboard.framebuffer())
+    {
+        eprintln!("Couldn't save screenshot {}: {}", screenshot_path, error);
+        return EXIT_IO;
+    }
+
+    match stop_reason {
+        StopReason::FrameLimit(_) | StopReason::CycleLimit
+            if !options.limits.breakpoints.is_empty() =>
+        {
+            EXIT_BREAKPOINT_MISSED
+        }
+        _ => EXIT_OK,
+    }
+}
+
+fn debug(motherboard: &mut Motherboard, options: &Options) -> i32 {
+    let mut debugger = Debugger::new();
+    for breakpoint in &options.limits.breakpoints {
+        debugger.add_breakpoint(*breakpoint);
+    }
+
+    let result = debugger
+        .run(motherboard, io::stdin().lock(), &mut io::stdout())
+        .and_then(|_| motherboard.stop_audio_recording())
+        .and_then(|_| motherboard.stop_trace())
+        .and_then(|_| motherboard.shutdown());
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        return EXIT_IO;
+    }
+    EXIT_OK
+}
 
-    println!("ABOVE IS CARTRIDGE ----- BELOW IS MEMORY IN motherboard");
+fn disassemble(rom_path: &str, symbols: Option<&str>) -> i32 {
+    let rom = match fs::read(rom_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Couldn't load rom {}: {}", rom_path, error);
+            return EXIT_IO;
+        }
+    };
+    let symbols = match symbols_path(rom_path, symbols) {
+        Some(symbols_path) => match Symbols::load_file(&symbols_path) {
+            Ok(symbols) => symbols,
+            Err(error) => {
+                eprintln!("Couldn't load symbols {}: {}", symbols_path, error);
+                return EXIT_IO;
+            }
+        },
+        None => Symbols::new(),
+    };
 
-    let byte0 = motherboard.memory.read_byte(0);
-    let byte1 = motherboard.memory.read_byte(1);
-    let byte2 = motherboard.memory.read_byte(2);
-    let byte3 = motherboard.memory.read_byte(3);
-    let byte4 = motherboard.memory.read_byte(4);
-    println!("Byte0: {}", byte0);
-    println!("Byte1: {}", byte1);
-    println!("Byte2: {}", byte2);
-    println!("Byte3: {}", byte3);
-    println!("Byte4: {}", byte4);
+    // Piping into head closes stdout early, that's fine
+    let _ = io::stdout()
+        .lock()
+        .write_all(disasm::disassemble_rom(&rom, &symbols).as_bytes());
+    EXIT_OK
 }
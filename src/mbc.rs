@@ -0,0 +1,1048 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+// 512 half-bytes, stored one per byte (upper nibble unused) like other emulators' .sav files
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+const ROM_BANK_N_START: u16 = 0x4000;
+const CARTRIDGE_RAM_START: u16 = 0xA000;
+
+// 4.194304 MHz / 4
+const M_CYCLES_PER_SECOND: u32 = 1_048_576;
+// 5 current + 5 latched registers as little endian u32s, then a u64 unix timestamp.
+// Same footer VBA-M, BGB, mGBA and SameBoy append to .sav files
+pub const RTC_SAVE_SIZE: usize = 48;
+
+// Memory bank controllers sit between the cpu and the cartridge rom/ram. Writes into the rom
+// range never reach the rom, they land in the controller's registers instead.
+// See https://gbdev.io/pandocs/MBCs.html
+pub trait Mbc: fmt::Debug {
+    // 0x0000-0x7FFF
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8;
+    // Which bank is mapped at a 0x0000-0x7FFF address, before wrapping to the rom size
+    fn rom_bank(&self, address: u16) -> usize;
+    // 0x0000-0x7FFF
+    fn write_register(&mut self, address: u16, value: u8);
+    // 0xA000-0xBFFF
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8;
+    // 0xA000-0xBFFF
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8);
+
+    fn rtc(&self) -> Option<&Rtc> {
+        None
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    fn rumble_active(&self) -> bool {
+        false
+    }
+}
+
+// Picks the controller from the cartridge type byte at 0x0147
+pub fn create_mbc(cartridge_type: u8) -> Option<Box<dyn Mbc>> {
+    match cartridge_type {
+        0x00 | 0x08 | 0x09 => Some(Box::new(RomOnly::new())),
+        0x01..=0x03 => Some(Box::new(Mbc1::new())),
+        0x05 | 0x06 => Some(Box::new(Mbc2::new())),
+        0x0F | 0x10 => Some(Box::new(Mbc3::new(true))),
+        0x11..=0x13 => Some(Box::new(Mbc3::new(false))),
+        0x19..=0x1B => Some(Box::new(Mbc5::new(false))),
+        0x1C..=0x1E => Some(Box::new(Mbc5::new(true))),
+        _ => None,
+    }
+}
+
+// MBC2 has its ram on the controller itself, so the header's ram size code is 0 for it
+pub fn builtin_ram_size(cartridge_type: u8) -> Option<usize> {
+    match cartridge_type {
+        0x05 | 0x06 => Some(MBC2_RAM_SIZE),
+        _ => None,
+    }
+}
+
+// Out of range banks wrap around, the upper bank bits just aren't wired to anything
+fn read_rom_bank(rom: &[u8], bank: usize, address: u16) -> u8 {
+    let bank_count = (rom.len() / ROM_BANK_SIZE).max(1);
+    let offset = (bank % bank_count) * ROM_BANK_SIZE + (address as usize % ROM_BANK_SIZE);
+
+    rom.get(offset).copied().unwrap_or(0xFF)
+}
+
+fn ram_offset(ram: &[u8], bank: usize, address: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+
+    let offset = bank * RAM_BANK_SIZE + (address - CARTRIDGE_RAM_START) as usize;
+    Some(offset % ram.len())
+}
+
+// 32 KiB carts with no banking, optionally with up to 8 KiB of always-enabled ram
+#[derive(Debug)]
+pub struct RomOnly {}
+
+impl RomOnly {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for RomOnly {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mbc for RomOnly {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        (address as usize) / ROM_BANK_SIZE
+    }
+
+    fn write_register(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        match ram_offset(ram, 0, address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if let Some(offset) = ram_offset(ram, 0, address) {
+            ram[offset] = value;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc1 {
+    ram_enabled: bool,
+    // 5 bit register, 0 is treated as 1
+    rom_bank_low: u8,
+    // 2 bit register, either ram bank or bits 5-6 of the rom bank
+    bank_high: u8,
+    // false = simple (0x0000-0x3FFF and ram locked to bank 0), true = advanced
+    advanced_banking_mode: bool,
+}
+
+impl Mbc1 {
+    pub fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank_low: 1,
+            bank_high: 0,
+            advanced_banking_mode: false,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking_mode {
+            self.bank_high as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for Mbc1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        read_rom_bank(rom, self.rom_bank(address), address)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < ROM_BANK_N_START {
+            if self.advanced_banking_mode {
+                (self.bank_high as usize) << 5
+            } else {
+                0
+            }
+        } else {
+            ((self.bank_high as usize) << 5) | self.rom_bank_low as usize
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Only the 5 bit value is checked for zero, so banks 0x20/0x40/0x60 are unreachable
+                let bank = value & 0b0001_1111;
+                self.rom_bank_low = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.bank_high = value & 0b0000_0011,
+            0x6000..=0x7FFF => self.advanced_banking_mode = value & 0b0000_0001 > 0,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match ram_offset(ram, self.ram_bank(), address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        if let Some(offset) = ram_offset(ram, self.ram_bank(), address) {
+            ram[offset] = value;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc2 {
+    ram_enabled: bool,
+    // 4 bit register, 0 is treated as 1
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        read_rom_bank(rom, self.rom_bank(address), address)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < ROM_BANK_N_START {
+            0
+        } else {
+            self.rom_bank as usize
+        }
+    }
+
+    // Both registers live in 0x0000-0x3FFF, address bit 8 picks which one gets written
+    fn write_register(&mut self, address: u16, value: u8) {
+        if address > 0x3FFF {
+            return;
+        }
+
+        if address & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            let bank = value & 0b0000_1111;
+            self.rom_bank = if bank == 0 { 1 } else { bank };
+        }
+    }
+
+    // Only 9 address bits are wired, so the 512 entries echo all the way up to 0xBFFF
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled || ram.is_empty() {
+            return 0xFF;
+        }
+
+        let offset = (address as usize & (MBC2_RAM_SIZE - 1)) % ram.len();
+        ram[offset] | 0xF0
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if !self.ram_enabled || ram.is_empty() {
+            return;
+        }
+
+        let offset = (address as usize & (MBC2_RAM_SIZE - 1)) % ram.len();
+        ram[offset] = value & 0x0F;
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc3 {
+    ram_and_rtc_enabled: bool,
+    // 7 bit register, 0 is treated as 1
+    rom_bank: u8,
+    // 0x00-0x03 selects a ram bank, 0x08-0x0C maps an rtc register into 0xA000-0xBFFF
+    ram_bank_or_rtc_select: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(has_rtc: bool) -> Self {
+        Self {
+            ram_and_rtc_enabled: false,
+            rom_bank: 1,
+            ram_bank_or_rtc_select: 0,
+            rtc: if has_rtc { Some(Rtc::new()) } else { None },
+        }
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        read_rom_bank(rom, self.rom_bank(address), address)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < ROM_BANK_N_START {
+            0
+        } else {
+            self.rom_bank as usize
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_and_rtc_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                let bank = value & 0b0111_1111;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.ram_bank_or_rtc_select = value,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_and_rtc_enabled {
+            return 0xFF;
+        }
+
+        match self.ram_bank_or_rtc_select {
+            0x00..=0x03 => match ram_offset(ram, self.ram_bank_or_rtc_select as usize, address) {
+                Some(offset) => ram[offset],
+                None => 0xFF,
+            },
+            0x08..=0x0C => match &self.rtc {
+                Some(rtc) => rtc.read_register(self.ram_bank_or_rtc_select),
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if !self.ram_and_rtc_enabled {
+            return;
+        }
+
+        match self.ram_bank_or_rtc_select {
+            0x00..=0x03 => {
+                if let Some(offset) = ram_offset(ram, self.ram_bank_or_rtc_select as usize, address)
+                {
+                    ram[offset] = value;
+                }
+            }
+            0x08..=0x0C => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_register(self.ram_bank_or_rtc_select, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+#[derive(Debug)]
+pub struct Mbc5 {
+    ram_enabled: bool,
+    // 9 bit register, and unlike the older controllers bank 0 can be mapped to 0x4000
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble_active: bool,
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble_active: false,
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        read_rom_bank(rom, self.rom_bank(address), address)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if address < ROM_BANK_N_START {
+            0
+        } else {
+            self.rom_bank as usize
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x0FF) | (((value & 0b0000_0001) as u16) << 8)
+            }
+            0x4000..=0x5FFF => {
+                // Rumble carts wire bit 3 to the motor instead of the ram bank
+                if self.has_rumble {
+                    self.rumble_active = value & 0b0000_1000 > 0;
+                    self.ram_bank = value & 0b0000_0111;
+                } else {
+                    self.ram_bank = value & 0b0000_1111;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match ram_offset(ram, self.ram_bank as usize, address) {
+            Some(offset) => ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        if let Some(offset) = ram_offset(ram, self.ram_bank as usize, address) {
+            ram[offset] = value;
+        }
+    }
+
+    fn rumble_active(&self) -> bool {
+        self.rumble_active
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    // Lower 8 bits of the 9 bit day counter
+    pub days_low: u8,
+    // Bit 0 = day counter bit 8, bit 6 = halt, bit 7 = day counter carry
+    pub days_high: u8,
+}
+
+impl RtcRegisters {
+    fn to_array(self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high,
+        ]
+    }
+
+    fn from_array(values: [u8; 5]) -> Self {
+        Self {
+            seconds: values[0] & 0b0011_1111,
+            minutes: values[1] & 0b0011_1111,
+            hours: values[2] & 0b0001_1111,
+            days_low: values[3],
+            days_high: values[4] & 0b1100_0001,
+        }
+    }
+}
+
+// MBC3 real time clock. Counts in emulated time by default so runs stay reproducible,
+// or follows the host's wall clock when sync_to_host is on.
+#[derive(Debug)]
+pub struct Rtc {
+    current: RtcRegisters,
+    latched: RtcRegisters,
+    // Latching needs a 0x00 write followed by a 0x01 write
+    latch_primed: bool,
+    // M-cycles into the current second
+    subsecond_cycles: u32,
+    sync_to_host: bool,
+    // Unix seconds the registers were last brought up to date with in host mode
+    host_timestamp: u64,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            current: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            latch_primed: false,
+            subsecond_cycles: 0,
+            sync_to_host: false,
+            host_timestamp: unix_timestamp(),
+        }
+    }
+
+    pub fn current(&self) -> RtcRegisters {
+        self.current
+    }
+
+    pub fn latched(&self) -> RtcRegisters {
+        self.latched
+    }
+
+    pub fn set_sync_to_host(&mut self, sync_to_host: bool) {
+        self.sync_to_host = sync_to_host;
+        self.host_timestamp = unix_timestamp();
+    }
+
+    fn is_halted(&self) -> bool {
+        self.current.days_high & 0b0100_0000 > 0
+    }
+
+    // Driven by the m-cycles each instruction took
+    pub fn step(&mut self, m_cycles: u32) {
+        if self.sync_to_host || self.is_halted() {
+            return;
+        }
+
+        self.subsecond_cycles += m_cycles;
+        while self.subsecond_cycles >= M_CYCLES_PER_SECOND {
+            self.subsecond_cycles -= M_CYCLES_PER_SECOND;
+            self.tick_second();
+        }
+    }
+
+    fn sync_with_host(&mut self) {
+        if !self.sync_to_host {
+            return;
+        }
+
+        let now = unix_timestamp();
+        let elapsed = now.saturating_sub(self.host_timestamp);
+        self.host_timestamp = now;
+        self.advance_seconds(elapsed);
+    }
+
+    fn advance_seconds(&mut self, seconds: u64) {
+        if self.is_halted() {
+            return;
+        }
+
+        // Anything past the day counter range just wraps with the carry set, no point ticking it all
+        let seconds = seconds.min(512 * 24 * 60 * 60 + 1);
+        for _ in 0..seconds {
+            self.tick_second();
+        }
+    }
+
+    // Registers only roll over when they hit their real limit, so out of range values written by
+    // the game count up to the bit width and wrap to 0 without carrying (same as hardware)
+    fn tick_second(&mut self) {
+        self.current.seconds = (self.current.seconds + 1) & 0b0011_1111;
+        if self.current.seconds != 60 {
+            return;
+        }
+        self.current.seconds = 0;
+
+        self.current.minutes = (self.current.minutes + 1) & 0b0011_1111;
+        if self.current.minutes != 60 {
+            return;
+        }
+        self.current.minutes = 0;
+
+        self.current.hours = (self.current.hours + 1) & 0b0001_1111;
+        if self.current.hours != 24 {
+            return;
+        }
+        self.current.hours = 0;
+
+        let days =
+            (((self.current.days_high & 0b0000_0001) as u16) << 8) | self.current.days_low as u16;
+        let days = days + 1;
+        self.current.days_low = (days & 0xFF) as u8;
+        self.current.days_high = (self.current.days_high & 0b1100_0000) | ((days >> 8) & 1) as u8;
+        if days > 0x1FF {
+            // Day counter overflowed, carry stays set until the game clears it
+            self.current.days_high |= 0b1000_0000;
+        }
+    }
+
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_primed && value == 0x01 {
+            self.sync_with_host();
+            self.latched = self.current;
+        }
+        self.latch_primed = value == 0x00;
+    }
+
+    fn read_register(&self, select: u8) -> u8 {
+        match select {
+            0x08 => self.latched.seconds,
+            0x09 => self.latched.minutes,
+            0x0A => self.latched.hours,
+            0x0B => self.latched.days_low,
+            0x0C => self.latched.days_high,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_register(&mut self, select: u8, value: u8) {
+        self.sync_with_host();
+
+        match select {
+            0x08 => {
+                // Writing seconds also resets the internal sub-second divider
+                self.current.seconds = value & 0b0011_1111;
+                self.subsecond_cycles = 0;
+            }
+            0x09 => self.current.minutes = value & 0b0011_1111,
+            0x0A => self.current.hours = value & 0b0001_1111,
+            0x0B => self.current.days_low = value,
+            0x0C => self.current.days_high = value & 0b1100_0001,
+            _ => {}
+        }
+
+        // Keep the latched copy in step so games can read back what they wrote
+        self.latched = self.current;
+    }
+
+    pub fn to_save_bytes(&mut self) -> [u8; RTC_SAVE_SIZE] {
+        self.sync_with_host();
+
+        let mut bytes = [0; RTC_SAVE_SIZE];
+        let registers = self
+            .current
+            .to_array()
+            .into_iter()
+            .chain(self.latched.to_array());
+        for (index, value) in registers.enumerate() {
+            bytes[index * 4..index * 4 + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        bytes[40..48].copy_from_slice(&unix_timestamp().to_le_bytes());
+
+        bytes
+    }
+
+    // Older emulators wrote a 4 byte timestamp (44 bytes total), both are accepted
+    pub fn load_save_bytes(&mut self, bytes: &[u8]) {
+        if bytes.len() < 44 {
+            return;
+        }
+
+        let register = |index: usize| {
+            u32::from_le_bytes([
+                bytes[index * 4],
+                bytes[index * 4 + 1],
+                bytes[index * 4 + 2],
+                bytes[index * 4 + 3],
+            ]) as u8
+        };
+        self.current = RtcRegisters::from_array([
+            register(0),
+            register(1),
+            register(2),
+            register(3),
+            register(4),
+        ]);
+        self.latched = RtcRegisters::from_array([
+            register(5),
+            register(6),
+            register(7),
+            register(8),
+            register(9),
+        ]);
+        self.subsecond_cycles = 0;
+
+        let saved_timestamp = if bytes.len() >= RTC_SAVE_SIZE {
+            u64::from_le_bytes(bytes[40..48].try_into().unwrap())
+        } else {
+            u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as u64
+        };
+
+        // Catch up on the time the game spent switched off
+        if self.sync_to_host {
+            self.host_timestamp = saved_timestamp;
+            self.sync_with_host();
+        }
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every byte of each bank holds its own bank number
+    fn build_banked_rom(bank_count: usize) -> Vec<u8> {
+        (0..bank_count * ROM_BANK_SIZE)
+            .map(|index| (index / ROM_BANK_SIZE) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn rom_only_ignores_register_writes() {
+        let rom = build_banked_rom(2);
+        let mut mbc = RomOnly::new();
+
+        mbc.write_register(0x2000, 0x05);
+
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    }
+
+    #[test]
+    fn mbc1_switches_rom_bank() {
+        let rom = build_banked_rom(32);
+        let mut mbc = Mbc1::new();
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 5);
+        assert_eq!(mbc.read_rom(&rom, 0x7FFF), 5);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    }
+
+    #[test]
+    fn mbc1_bank_zero_selects_bank_one() {
+        let rom = build_banked_rom(32);
+        let mut mbc = Mbc1::new();
+
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+
+        // Only the low 5 bits are compared, so 0x20 gets bumped to bank 1 as well
+        mbc.write_register(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    }
+
+    #[test]
+    fn mbc1_upper_rom_bank_bits() {
+        let rom = build_banked_rom(128);
+        let mut mbc = Mbc1::new();
+
+        mbc.write_register(0x2000, 0x02);
+        mbc.write_register(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x22);
+
+        // Simple mode keeps bank 0 at 0x0000-0x3FFF
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x00);
+
+        // Advanced mode lets the upper bits through to the first bank area too
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x20);
+    }
+
+    #[test]
+    fn mbc1_rom_bank_wraps_to_rom_size() {
+        let rom = build_banked_rom(4);
+        let mut mbc = Mbc1::new();
+
+        mbc.write_register(0x2000, 0x06);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 2);
+    }
+
+    #[test]
+    fn mbc1_ram_needs_enabling() {
+        let mut ram = vec![0; RAM_BANK_SIZE];
+        let mut mbc = Mbc1::new();
+
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(ram[0], 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x42);
+
+        mbc.write_register(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn mbc1_ram_banking_only_in_advanced_mode() {
+        let mut ram = vec![0; 4 * RAM_BANK_SIZE];
+        let mut mbc = Mbc1::new();
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x02);
+
+        mbc.write_ram(&mut ram, 0xA001, 0x11);
+        assert_eq!(ram[0x0001], 0x11);
+
+        mbc.write_register(0x6000, 0x01);
+        mbc.write_ram(&mut ram, 0xA001, 0x22);
+        assert_eq!(ram[2 * RAM_BANK_SIZE + 0x0001], 0x22);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0x22);
+    }
+
+    fn enabled_mbc3_with_rtc() -> Mbc3 {
+        let mut mbc = Mbc3::new(true);
+        mbc.write_register(0x0000, 0x0A);
+        mbc
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.write_register(0x6000, 0x00);
+        mbc.write_register(0x6000, 0x01);
+    }
+
+    #[test]
+    fn mbc3_switches_7bit_rom_bank() {
+        let rom = build_banked_rom(128);
+        let mut mbc = Mbc3::new(false);
+
+        mbc.write_register(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x01);
+    }
+
+    #[test]
+    fn mbc3_switches_ram_bank() {
+        let mut ram = vec![0; 4 * RAM_BANK_SIZE];
+        let mut mbc = enabled_mbc3_with_rtc();
+
+        mbc.write_register(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xA010, 0x33);
+
+        assert_eq!(ram[3 * RAM_BANK_SIZE + 0x10], 0x33);
+        assert_eq!(mbc.read_ram(&ram, 0xA010), 0x33);
+    }
+
+    #[test]
+    fn rtc_counts_emulated_seconds() {
+        let ram = vec![];
+        let mut mbc = enabled_mbc3_with_rtc();
+
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND * 61);
+        latch(&mut mbc);
+
+        mbc.write_register(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 1);
+        mbc.write_register(0x4000, 0x09);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 1);
+    }
+
+    #[test]
+    fn rtc_reads_latched_value_until_relatched() {
+        let ram = vec![];
+        let mut mbc = enabled_mbc3_with_rtc();
+        mbc.write_register(0x4000, 0x08);
+
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND * 5);
+        latch(&mut mbc);
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND * 5);
+
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 5);
+
+        // 0x01 on its own doesn't latch
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 5);
+
+        latch(&mut mbc);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 10);
+    }
+
+    #[test]
+    fn rtc_halt_stops_counting() {
+        let mut ram = vec![];
+        let mut mbc = enabled_mbc3_with_rtc();
+
+        mbc.write_register(0x4000, 0x0C);
+        mbc.write_ram(&mut ram, 0xA000, 0b0100_0000);
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND * 10);
+        latch(&mut mbc);
+
+        assert_eq!(mbc.rtc().unwrap().current().seconds, 0);
+    }
+
+    #[test]
+    fn rtc_day_counter_carry() {
+        let mut ram = vec![];
+        let mut mbc = enabled_mbc3_with_rtc();
+
+        // Day 511, 23:59:59
+        mbc.write_register(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xA000, 59);
+        mbc.write_register(0x4000, 0x09);
+        mbc.write_ram(&mut ram, 0xA000, 59);
+        mbc.write_register(0x4000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 23);
+        mbc.write_register(0x4000, 0x0B);
+        mbc.write_ram(&mut ram, 0xA000, 0xFF);
+        mbc.write_register(0x4000, 0x0C);
+        mbc.write_ram(&mut ram, 0xA000, 0b0000_0001);
+
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND);
+
+        let current = mbc.rtc().unwrap().current();
+        assert_eq!(current.seconds, 0);
+        assert_eq!(current.minutes, 0);
+        assert_eq!(current.hours, 0);
+        assert_eq!(current.days_low, 0);
+        assert_eq!(current.days_high, 0b1000_0000);
+    }
+
+    #[test]
+    fn rtc_out_of_range_seconds_wrap_without_carry() {
+        let mut ram = vec![];
+        let mut mbc = enabled_mbc3_with_rtc();
+
+        mbc.write_register(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xA000, 63);
+        mbc.rtc_mut().unwrap().step(M_CYCLES_PER_SECOND);
+
+        let current = mbc.rtc().unwrap().current();
+        assert_eq!(current.seconds, 0);
+        assert_eq!(current.minutes, 0);
+    }
+
+    #[test]
+    fn rtc_save_round_trip() {
+        let mut rtc = Rtc::new();
+        rtc.step(M_CYCLES_PER_SECOND * 3723);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        let bytes = rtc.to_save_bytes();
+        let mut loaded = Rtc::new();
+        loaded.load_save_bytes(&bytes);
+
+        assert_eq!(loaded.current(), rtc.current());
+        assert_eq!(loaded.latched(), rtc.latched());
+        assert_eq!(loaded.current().hours, 1);
+        assert_eq!(loaded.current().minutes, 2);
+        assert_eq!(loaded.current().seconds, 3);
+    }
+
+    #[test]
+    fn mbc5_9bit_rom_bank() {
+        let rom: Vec<u8> = (0..512 * ROM_BANK_SIZE)
+            .map(|index| ((index / ROM_BANK_SIZE) & 0xFF) as u8)
+            .collect();
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_register(0x2000, 0x05);
+        mbc.write_register(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x05);
+        assert_eq!(mbc.rom_bank, 0x105);
+
+        // Bank 0 really is bank 0 on MBC5
+        mbc.write_register(0x2000, 0x00);
+        mbc.write_register(0x3000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x00);
+    }
+
+    #[test]
+    fn mbc5_16_ram_banks() {
+        let mut ram = vec![0; 16 * RAM_BANK_SIZE];
+        let mut mbc = Mbc5::new(false);
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_register(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0xA000, 0x5A);
+
+        assert_eq!(ram[15 * RAM_BANK_SIZE], 0x5A);
+        assert!(!mbc.rumble_active());
+    }
+
+    #[test]
+    fn mbc5_rumble_bit() {
+        let mut ram = vec![0; 16 * RAM_BANK_SIZE];
+        let mut mbc = Mbc5::new(true);
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_register(0x4000, 0b0000_1010);
+        mbc.write_ram(&mut ram, 0xA000, 0x5A);
+
+        assert!(mbc.rumble_active());
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x5A);
+
+        mbc.write_register(0x4000, 0x00);
+        assert!(!mbc.rumble_active());
+    }
+
+    #[test]
+    fn mbc2_address_bit_8_selects_register() {
+        let rom = build_banked_rom(16);
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        let mut mbc = Mbc2::new();
+
+        // Bit 8 set, rom bank
+        mbc.write_register(0x2100, 0x0A);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x0A);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        // Bit 8 clear, ram enable
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x07);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xF7);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x0A);
+    }
+
+    #[test]
+    fn mbc2_ram_is_4bit_and_echoes() {
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        let mut mbc = Mbc2::new();
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_ram(&mut ram, 0xA1FF, 0xAB);
+
+        assert_eq!(ram[0x1FF], 0x0B);
+        assert_eq!(mbc.read_ram(&ram, 0xA1FF), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xA3FF), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xBFFF), 0xFB);
+    }
+}
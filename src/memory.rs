@@ -1,4 +1,13 @@
-use crate::cartridge::Cartridge;
+use std::cell::RefCell;
+use std::io;
+
+use crate::apu::{self, Apu};
+use crate::cartridge::{Cartridge, CartridgeError};
+use crate::gpu::{self, Gpu};
+use crate::interrupts::Interrupts;
+use crate::joypad::{self, Joypad};
+use crate::serial::{self, Serial};
+use crate::timer::{self, Timer};
 
 // TODO: Check if memory banks are off by one (might need to add +1 t0 each of them aside from first? UNSURE)
 const CARTRIDGE_ROM_BANK_0_START: u16 = 0x0000;
@@ -18,16 +27,56 @@ const OAM_END: u16 = 0xFE9F;
 const PROHIBITED_RAM_START: u16 = 0xFEA0;
 const PROHIBITED_RAM_END: u16 = 0xFEFF;
 const IO_REGISTERS_START: u16 = 0xFF00;
+const INTERRUPT_FLAG_REGISTER: u16 = 0xFF0F;
 const IO_REGISTERS_END: u16 = 0xFF7F;
 const HRAM_START: u16 = 0xFF80;
 const HRAM_END: u16 = 0xFFFE;
+const BOOT_ROM_START: u16 = 0x0000;
+const BOOT_ROM_END: u16 = 0x00FF;
+const BOOT_ROM_DISABLE: u16 = 0xFF50;
 const IE_REGISTER: u16 = 0xFFFF;
 
+const OAM_DMA_LENGTH: u16 = 0xA0;
+
 pub struct Memory {
     // TODO probably a better way to handle segments of an array
     _memory: Vec<u8>,
 
-    cartridge: Cartridge,
+    pub cartridge: Cartridge,
+    pub interrupts: Interrupts,
+    pub timer: Timer,
+    pub joypad: Joypad,
+    pub serial: Serial,
+    pub gpu: Gpu,
+    pub apu: Apu,
+
+    oam_dma: Option<OamDma>,
+    // Sits over the start of the cartridge until anything with bit 0 set goes to 0xFF50
+    boot_rom: Option<Vec<u8>>,
+
+    watchpoints: Vec<(u16, Access)>,
+    // Filled in from read_byte, which only has &self
+    watch_hits: RefCell<Vec<WatchHit>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// A cpu access that matched a watchpoint. Dma and the debugger's own peeks don't count.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub access: Access,
+    pub value: u8,
+}
+
+// Copies XX00-XX9F into OAM, one byte per m-cycle
+struct OamDma {
+    source: u16,
+    index: u16,
 }
 
 impl Memory {
@@ -35,47 +84,253 @@ impl Memory {
         Self {
             _memory: vec![0; (0xFFFF + 1)],
             cartridge: Cartridge::new(),
+            interrupts: Interrupts::new(),
+            timer: Timer::new(),
+            joypad: Joypad::new(),
+            serial: Serial::new(),
+            gpu: Gpu::new(),
+            apu: Apu::new(),
+            oam_dma: None,
+            boot_rom: None,
+            watchpoints: Vec::new(),
+            watch_hits: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn load_rom_file(&mut self, file_path: &str) {
-        self.cartridge.load_rom_file(file_path);
+    pub fn load_rom_file(&mut self, file_path: &str) -> Result<(), CartridgeError> {
+        self.cartridge.load_rom_file(file_path)
+    }
+
+    pub fn load_boot_rom(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        let expected_length = (BOOT_ROM_END - BOOT_ROM_START + 1) as usize;
+        if bytes.len() != expected_length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "boot rom is {} bytes, expected {}",
+                    bytes.len(),
+                    expected_length
+                ),
+            ));
+        }
+
+        self.boot_rom = Some(bytes);
+        Ok(())
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    // Leaves the io registers the way the DMG boot rom does (https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers)
+    pub fn skip_boot_rom(&mut self) {
+        self.boot_rom = None;
+
+        self.timer.set_div_counter(0xABCC);
+        self.interrupts.write_flags(0xE1);
+
+        // NRx4 go in without the trigger bit, they read back the same and a trigger would restart
+        // the channels at full volume
+        for (address, value) in [
+            (0xFF26, 0xF1),
+            (0xFF10, 0x80),
+            (0xFF11, 0xBF),
+            (0xFF12, 0xF3),
+            (0xFF13, 0xFF),
+            (0xFF14, 0x3F),
+            (0xFF16, 0x3F),
+            (0xFF17, 0x00),
+            (0xFF18, 0xFF),
+            (0xFF19, 0x3F),
+            (0xFF1A, 0x7F),
+            (0xFF1B, 0xFF),
+            (0xFF1C, 0x9F),
+            (0xFF1D, 0xFF),
+            (0xFF1E, 0x3F),
+            (0xFF20, 0xFF),
+            (0xFF21, 0x00),
+            (0xFF22, 0x00),
+            (0xFF23, 0x3F),
+            (0xFF24, 0x77),
+            (0xFF25, 0xF3),
+            (0xFF40, 0x91),
+            (0xFF47, 0xFC),
+        ] {
+            self.write_byte(address, value);
+        }
+        self.apu.skip_boot_chime();
+    }
+
+    // Advance everything on the bus that keeps time on its own
+    pub fn step(&mut self, m_cycles: u32) {
+        self.step_oam_dma(m_cycles);
+        self.cartridge.step(m_cycles);
+        self.timer.step(m_cycles, &mut self.interrupts);
+        self.serial.step(m_cycles, &mut self.interrupts);
+        self.gpu.step(m_cycles * 4, &mut self.interrupts);
+        self.apu.step(m_cycles * 4);
+    }
+
+    pub fn oam_dma_active(&self) -> bool {
+        self.oam_dma.is_some()
+    }
+
+    fn start_oam_dma(&mut self, value: u8) {
+        self.oam_dma = Some(OamDma {
+            source: (value as u16) << 8,
+            index: 0,
+        });
     }
 
-    // TODO: Shouldn't we write to both the memory 
+    // Only the external and vram buses conflict with the dma, hram and the registers stay reachable
+    fn blocked_by_oam_dma(&self, address: u16) -> bool {
+        self.oam_dma.is_some() && !(IO_REGISTERS_START..=IE_REGISTER).contains(&address)
+    }
+
+    fn step_oam_dma(&mut self, m_cycles: u32) {
+        for _ in 0..m_cycles {
+            let Some(dma) = &mut self.oam_dma else {
+                return;
+            };
+
+            // The dma unit sees echo ram as the wram it mirrors
+            let mut source = dma.source + dma.index;
+            if source >= PROHIBITED_ECHO_RAM_START {
+                source -= PROHIBITED_ECHO_RAM_START - WRAM_START;
+            }
+            let destination = OAM_START + dma.index;
+
+            dma.index += 1;
+            if dma.index == OAM_DMA_LENGTH {
+                self.oam_dma = None;
+            }
+
+            let byte = self.bus_read_byte(source);
+            self.gpu.write_byte(destination, byte);
+        }
+    }
+
+    // While a dma is running whatever it blocks reads open bus
     pub fn read_byte(&self, address: u16) -> u8 {
+        let value = if self.blocked_by_oam_dma(address) {
+            0xFF
+        } else {
+            self.bus_read_byte(address)
+        };
+
+        self.check_watchpoints(address, Access::Read, value);
+        value
+    }
+
+    // For debuggers: no watchpoints, no dma lockout, and the unusable areas read back instead of panicking
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        match address {
+            PROHIBITED_ECHO_RAM_START..=PROHIBITED_ECHO_RAM_END => {
+                self._memory[(address - (PROHIBITED_ECHO_RAM_START - WRAM_START)) as usize]
+            }
+            PROHIBITED_RAM_START..=PROHIBITED_RAM_END => 0xFF,
+            _ => self.bus_read_byte(address),
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16, access: Access) {
+        if !self.watchpoints.contains(&(address, access)) {
+            self.watchpoints.push((address, access));
+        }
+    }
+
+    pub fn remove_watchpoints(&mut self, address: u16) {
+        self.watchpoints.retain(|(watched, _)| *watched != address);
+    }
+
+    pub fn watchpoints(&self) -> &[(u16, Access)] {
+        &self.watchpoints
+    }
+
+    // Everything that matched since the last call
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watch_hits.take()
+    }
+
+    fn check_watchpoints(&self, address: u16, access: Access, value: u8) {
+        if self.watchpoints.contains(&(address, access)) {
+            self.watch_hits.borrow_mut().push(WatchHit {
+                address,
+                access,
+                value,
+            });
+        }
+    }
+
+    // Single registers are carved out of the io range ahead of its catch-all arm
+    #[allow(clippy::match_overlapping_arm)]
+    fn bus_read_byte(&self, address: u16) -> u8 {
         match address {
+            BOOT_ROM_START..=BOOT_ROM_END if self.boot_rom.is_some() => {
+                self.boot_rom.as_ref().unwrap()[address as usize]
+            }
             CARTRIDGE_ROM_BANK_0_START..=CARTRIDGE_ROM_BANK_N_END => {
                 self.cartridge.read_byte(address) // TODO
             }
-            VRAM_START..=VRAM_END => self._memory[address as usize],
+            VRAM_START..=VRAM_END => self.gpu.read_byte(address),
             CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => self.cartridge.read_byte(address),
             WRAM_START..=WRAM_END => self._memory[address as usize],
             PROHIBITED_ECHO_RAM_START..=PROHIBITED_ECHO_RAM_END => panic!("PROHIBITED_ECHO_RAM"),
-            OAM_START..=OAM_END => self._memory[address as usize],
+            OAM_START..=OAM_END => self.gpu.read_byte(address),
             PROHIBITED_RAM_START..=PROHIBITED_RAM_END => panic!("PROHIBITED_RAM"),
+            joypad::JOYPAD_ADDRESS => self.joypad.read_byte(),
+            serial::SB_ADDRESS..=serial::SC_ADDRESS => self.serial.read_byte(address),
+            timer::DIV_ADDRESS..=timer::TAC_ADDRESS => self.timer.read_byte(address),
+            INTERRUPT_FLAG_REGISTER => self.interrupts.read_flags(),
+            apu::APU_START..=apu::APU_END => self.apu.read_byte(address),
+            gpu::LCD_CONTROL_ADDRESS..=gpu::WINDOW_X_ADDRESS => self.gpu.read_byte(address),
+            BOOT_ROM_DISABLE => 0xFF,
             IO_REGISTERS_START..=IO_REGISTERS_END => self._memory[address as usize],
             HRAM_START..=HRAM_END => self._memory[address as usize],
-            IE_REGISTER => self._memory[address as usize],
+            IE_REGISTER => self.interrupts.read_enabled(),
             _ => panic!("Unexpected Memory::read_byte {}", address),
         }
     }
 
+    // TODO: Shouldn't we write to both the memory
     // TODO: Look over/talk with tint (does this follow the endianness of the machine?)
+    #[allow(clippy::match_overlapping_arm)]
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.check_watchpoints(address, Access::Write, value);
+
+        if self.blocked_by_oam_dma(address) {
+            return;
+        }
+
         match address {
             CARTRIDGE_ROM_BANK_0_START..=CARTRIDGE_ROM_BANK_N_END => {
                 self.cartridge.write_byte(address, value)
             }
-            VRAM_START..=VRAM_END => self._memory[address as usize] = value,
+            VRAM_START..=VRAM_END => self.gpu.write_byte(address, value),
             CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => self.cartridge.write_byte(address, value),
             WRAM_START..=WRAM_END => self._memory[address as usize] = value,
             PROHIBITED_ECHO_RAM_START..=PROHIBITED_ECHO_RAM_END => panic!("PROHIBITED_ECHO_RAM"),
-            OAM_START..=OAM_END => self._memory[address as usize] = value,
+            OAM_START..=OAM_END => self.gpu.write_byte(address, value),
             PROHIBITED_RAM_START..=PROHIBITED_RAM_END => panic!("PROHIBITED_RAM"),
+            joypad::JOYPAD_ADDRESS => self.joypad.write_byte(value, &mut self.interrupts),
+            serial::SB_ADDRESS..=serial::SC_ADDRESS => self.serial.write_byte(address, value),
+            timer::DIV_ADDRESS..=timer::TAC_ADDRESS => self.timer.write_byte(address, value),
+            INTERRUPT_FLAG_REGISTER => self.interrupts.write_flags(value),
+            apu::APU_START..=apu::APU_END => self.apu.write_byte(address, value),
+            gpu::OAM_DMA_SOURCE_ADDRESS => {
+                self.gpu.write_byte(address, value);
+                self.start_oam_dma(value);
+            }
+            gpu::LCD_CONTROL_ADDRESS..=gpu::WINDOW_X_ADDRESS => self.gpu.write_byte(address, value),
+            BOOT_ROM_DISABLE => {
+                // Can't be mapped back in
+                if value & 0x01 > 0 {
+                    self.boot_rom = None;
+                }
+            }
             IO_REGISTERS_START..=IO_REGISTERS_END => self._memory[address as usize] = value,
             HRAM_START..=HRAM_END => self._memory[address as usize] = value,
-            IE_REGISTER => self._memory[address as usize] = value,
+            IE_REGISTER => self.interrupts.write_enabled(value),
             _ => panic!("Unexpected Memory::write_byte {} {}", address, value),
         };
     }
@@ -83,3 +338,196 @@ impl Memory {
         
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::build_test_rom;
+
+    use super::*;
+
+    #[test]
+    fn oam_dma_copies_over_160_m_cycles() {
+        let mut memory = Memory::new();
+        for i in 0..0xA0 {
+            memory.write_byte(0xC100 + i, i as u8 + 1);
+        }
+
+        memory.write_byte(0xFF46, 0xC1);
+        assert!(memory.oam_dma_active());
+        assert_eq!(memory.read_byte(0xFF46), 0xC1);
+
+        memory.step(80);
+        assert_eq!(memory.gpu.read_byte(0xFE4F), 0x50);
+        assert_eq!(memory.gpu.read_byte(0xFE50), 0x00);
+
+        memory.step(80);
+        assert!(!memory.oam_dma_active());
+        assert_eq!(memory.read_byte(0xFE00), 0x01);
+        assert_eq!(memory.read_byte(0xFE9F), 0xA0);
+        assert_eq!(memory.read_byte(0xFF46), 0xC1);
+    }
+
+    #[test]
+    fn only_hram_and_registers_reachable_during_oam_dma() {
+        let mut memory = Memory::new();
+        memory.write_byte(0xC000, 0x12);
+        memory.write_byte(0xFF80, 0x34);
+
+        memory.write_byte(0xFF46, 0xC0);
+        assert_eq!(memory.read_byte(0xC000), 0xFF);
+        assert_eq!(memory.read_byte(0xFF80), 0x34);
+
+        memory.write_byte(0xC000, 0x56);
+        memory.write_byte(0xFF81, 0x78);
+        memory.write_byte(0xFF06, 0x9A); // TMA
+        memory.write_byte(0xFFFF, 0x1F);
+        assert_eq!(memory.read_byte(0xFF06), 0x9A);
+        assert_eq!(memory.read_byte(0xFFFF), 0x1F);
+
+        memory.step(160);
+        assert_eq!(memory.read_byte(0xC000), 0x12);
+        assert_eq!(memory.read_byte(0xFF81), 0x78);
+    }
+
+    #[test]
+    fn oam_dma_restarts_on_ff46_write() {
+        let mut memory = Memory::new();
+        memory.write_byte(0xC100, 0x11);
+        memory.write_byte(0xC200, 0x22);
+
+        memory.write_byte(0xFF46, 0xC1);
+        memory.step(80);
+        memory.write_byte(0xFF46, 0xC2);
+        assert_eq!(memory.read_byte(0xFF46), 0xC2);
+
+        // A fresh 160 cycles from the second write
+        memory.step(159);
+        assert!(memory.oam_dma_active());
+        memory.step(1);
+        assert!(!memory.oam_dma_active());
+        assert_eq!(memory.read_byte(0xFE00), 0x22);
+    }
+
+    #[test]
+    fn oam_dma_from_echo_ram_reads_wram() {
+        let mut memory = Memory::new();
+        memory.write_byte(0xC000, 0x42);
+
+        memory.write_byte(0xFF46, 0xE0);
+        memory.step(160);
+
+        assert_eq!(memory.read_byte(0xFE00), 0x42);
+    }
+
+    #[test]
+    fn boot_rom_unmaps_on_ff50_write() {
+        let mut memory = Memory::new();
+        let mut rom = build_test_rom(0x00, 0x00, 0x00);
+        rom[0x0000] = 0xAA;
+        rom[0x0100] = 0xBB;
+        memory.cartridge = Cartridge::from_bytes(rom).unwrap();
+
+        let mut boot_rom = vec![0x31; 0x100];
+        boot_rom[0x00] = 0x42;
+        memory.load_boot_rom(boot_rom).unwrap();
+
+        assert_eq!(memory.read_byte(0x0000), 0x42);
+        assert_eq!(memory.read_byte(0x00FF), 0x31);
+        assert_eq!(memory.read_byte(0x0100), 0xBB);
+
+        memory.write_byte(0xFF50, 0x00);
+        assert!(memory.boot_rom_mapped());
+        memory.write_byte(0xFF50, 0x01);
+        assert!(!memory.boot_rom_mapped());
+        assert_eq!(memory.read_byte(0x0000), 0xAA);
+    }
+
+    #[test]
+    fn watchpoints_record_cpu_accesses() {
+        let mut memory = Memory::new();
+        memory.add_watchpoint(0xC000, Access::Write);
+        memory.add_watchpoint(0xC001, Access::Read);
+
+        memory.write_byte(0xC000, 0x12);
+        memory.write_byte(0xC001, 0x34);
+        memory.read_byte(0xC000);
+        memory.read_byte(0xC001);
+        memory.peek_byte(0xC001);
+
+        assert_eq!(
+            memory.take_watch_hits(),
+            [
+                WatchHit {
+                    address: 0xC000,
+                    access: Access::Write,
+                    value: 0x12
+                },
+                WatchHit {
+                    address: 0xC001,
+                    access: Access::Read,
+                    value: 0x34
+                },
+            ]
+        );
+        assert!(memory.take_watch_hits().is_empty());
+
+        memory.remove_watchpoints(0xC000);
+        memory.write_byte(0xC000, 0x56);
+        assert!(memory.take_watch_hits().is_empty());
+    }
+
+    #[test]
+    fn peek_reaches_echo_ram() {
+        let mut memory = Memory::new();
+        memory.write_byte(0xC123, 0x42);
+
+        assert_eq!(memory.peek_byte(0xE123), 0x42);
+        assert_eq!(memory.peek_byte(0xFEA0), 0xFF);
+    }
+
+    #[test]
+    fn boot_rom_has_to_be_256_bytes() {
+        let mut memory = Memory::new();
+        assert!(memory.load_boot_rom(vec![0; 0x200]).is_err());
+        assert!(!memory.boot_rom_mapped());
+    }
+
+    #[test]
+    fn skip_boot_rom_leaves_channel_1_silent() {
+        let mut memory = Memory::new();
+        memory.skip_boot_rom();
+        memory.step(4096);
+
+        // A retriggered channel 1 would be a full volume square wave, swinging between samples
+        let left: Vec<f32> = memory.apu.take_samples().into_iter().step_by(2).collect();
+        assert!(!left.is_empty());
+        for pair in left.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 0.01);
+        }
+        assert_eq!(memory.read_byte(0xFF14), 0xBF);
+        assert_eq!(memory.read_byte(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn skip_boot_rom_io_defaults() {
+        let mut memory = Memory::new();
+        memory.skip_boot_rom();
+
+        assert_eq!(memory.read_byte(0xFF00), 0xCF);
+        assert_eq!(memory.read_byte(0xFF02), 0x7E);
+        assert_eq!(memory.read_byte(0xFF04), 0xAB);
+        assert_eq!(memory.read_byte(0xFF07), 0xF8);
+        assert_eq!(memory.read_byte(0xFF0F), 0xE1);
+        assert_eq!(memory.read_byte(0xFF10), 0x80);
+        assert_eq!(memory.read_byte(0xFF11), 0xBF);
+        assert_eq!(memory.read_byte(0xFF12), 0xF3);
+        assert_eq!(memory.read_byte(0xFF1A), 0x7F);
+        assert_eq!(memory.read_byte(0xFF1C), 0x9F);
+        assert_eq!(memory.read_byte(0xFF24), 0x77);
+        assert_eq!(memory.read_byte(0xFF25), 0xF3);
+        assert_eq!(memory.read_byte(0xFF26), 0xF1);
+        assert_eq!(memory.read_byte(0xFF40), 0x91);
+        assert_eq!(memory.read_byte(0xFF47), 0xFC);
+        assert_eq!(memory.read_byte(0xFFFF), 0x00);
+    }
+}
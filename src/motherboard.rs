@@ -1,18 +1,33 @@
 // TODO: BOTH CPU AND MOTHERBORD HAVE THEIR OWN MEMORY!!!! HOW DO WE WANT TO HANDLE THIS.
 
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
 use crate::{
-    cartridge::Cartridge,
+    cartridge::{Cartridge, CartridgeError},
     clock::Clock,
     cpu::Cpu,
     cpu_logic::{
         execute_one_byte_opcode, execute_prefix_opcode, execute_three_byte_opcode,
-        execute_two_byte_opcode,
+        execute_two_byte_opcode, load_byte_into_stack_after_decrement_stack_pointer,
     },
+    gpu::Renderer,
+    joypad::Button,
     memory::Memory,
     opcode::OneByteOpCode,
-    registers::{RegWord, Registers},
+    registers::{RegByte, RegWord, Registers},
+    serial::SerialLink,
+    symbols::Symbols,
+    wav::WavWriter,
 };
 
+// 154 lines of 456 dots
+pub const M_CYCLES_PER_FRAME: u64 = 17556;
+// Samples are handed to the recorder in chunks rather than after every instruction
+const AUDIO_RECORDING_CHUNK: usize = 4096;
+
 // TODO: General note/concern, making everything private, will fix/make public
 // > After further along, wanted to try habit of everything private until needing to
 // > turn it into public/learning experience.
@@ -24,6 +39,21 @@ pub struct Motherboard {
     pub cartridge: Cartridge,
     pub clock: Clock,
     pub cpu: Cpu,
+    // HALT: asleep until IE & IF is nonzero
+    pub halted: bool,
+    // STOP: asleep until a joypad press
+    pub stopped: bool,
+    // HALT with IME off and an interrupt already pending skips the PC increment on the next fetch
+    pub halt_bug: bool,
+    // Empty unless a .sym file was loaded
+    pub symbols: Symbols,
+
+    audio_recorder: Option<WavWriter<BufWriter<File>>>,
+    // A failed write ends the recording, the error is handed back on stop
+    audio_recorder_error: Option<io::Error>,
+    trace: Option<BufWriter<File>>,
+    // Same as for the recorder, a failed write ends the trace
+    trace_error: Option<io::Error>,
 }
 
 impl Motherboard {
@@ -36,33 +66,77 @@ impl Motherboard {
             cartridge: Cartridge::new(),
             clock: Clock::new(),
             cpu: Cpu::new(),
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            symbols: Symbols::new(),
+            audio_recorder: None,
+            audio_recorder_error: None,
+            trace: None,
+            trace_error: None,
         }
     }
 
     // Get next instruction from memory by reading program counter
     // > and increment program counter
     fn fetch_next_byte(&mut self) -> u8 {
-        println!(
-            "pc at beginning of fetch_next_byte: {}",
-            self.registers.read_word(&RegWord::PC)
-        );
         let byte = self
             .memory
             .read_byte(self.registers.read_word(&RegWord::PC));
-        self.registers.increment_pc();
-        println!(
-            "pc at end of fetch_next_byte: {}",
-            self.registers.read_word(&RegWord::PC)
-        );
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.increment_pc();
+        }
         byte
     }
 
-    pub fn load_rom_file(&mut self, file_path: &str) {
-        self.memory.load_rom_file(file_path);
+    // Without a boot rom loaded first, this starts straight from the post-boot state at 0x0100.
+    // The outgoing cartridge's save is written first. Failing that doesn't stop the new rom
+    // loading, the save result comes back inside the Ok for the caller to report.
+    pub fn load_rom_file(&mut self, file_path: &str) -> Result<io::Result<()>, CartridgeError> {
+        let save_result = self.memory.cartridge.flush_save();
+        self.memory.load_rom_file(file_path)?;
+
+        if !self.memory.boot_rom_mapped() {
+            self.skip_boot_rom();
+        }
+        Ok(save_result)
+    }
+
+    // Writes out the battery save, call before exiting
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        self.memory.cartridge.flush_save()
+    }
+
+    // A 256 byte DMG boot rom, run from 0x0000 until it unmaps itself through 0xFF50
+    pub fn load_boot_rom_file(&mut self, file_path: &str) -> io::Result<()> {
+        self.memory.load_boot_rom(fs::read(file_path)?)?;
+        self.registers = Registers::new();
+        Ok(())
+    }
+
+    pub fn load_symbol_file(&mut self, file_path: &str) -> io::Result<()> {
+        self.symbols = Symbols::load_file(file_path)?;
+        Ok(())
+    }
+
+    // Rom labels only match in the bank the mbc has mapped there right now
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        let bank = match address {
+            0x0000..=0x7FFF => Some(self.memory.cartridge.rom_bank(address) as u16),
+            _ => None,
+        };
+        self.symbols.label(bank, address)
+    }
+
+    pub fn skip_boot_rom(&mut self) {
+        self.registers = Registers::post_boot();
+        self.memory.skip_boot_rom();
     }
 
     // Get length of instruction (How many bytes of data needed, always between 1 (the initial bit) and 3 (two additional immediate bytes))
-    fn get_instruction_length(instruction: u8) -> u8 {
+    pub(crate) fn get_instruction_length(instruction: u8) -> u8 {
         // TEMP: Working glossary for comments following instructions
         // Add/sub/load X Z = perform operation with X as destination/data performed UPON by z, Z is the data performing UNTO x
         // e.g. load x z = load z into x
@@ -331,7 +405,7 @@ impl Motherboard {
             // Ex
             0xE0 => 2, // LDH (a8) A
             0xE1 => 1, // POP HL
-            0xE2 => 2, // LD (C) A
+            0xE2 => 1, // LD (C) A
             0xE3 => 1, // BLANK (TODO: How to handle blanks/nothingburgers? Also what to return?)
             0xE4 => 1, // BLANK
             0xE5 => 1, // PUSH HL
@@ -349,7 +423,7 @@ impl Motherboard {
             // Fx
             0xF0 => 2, // LDH A (a8)
             0xF1 => 1, // POP AF
-            0xF2 => 2, // LD A (C)
+            0xF2 => 1, // LD A (C)
             0xF3 => 1, // DI (TODO: Direct interrupt maybe?)
             0xF4 => 1, // BLANK
             0xF5 => 1, // PUSH AF
@@ -395,7 +469,218 @@ impl Motherboard {
     }
 
     // TODO: CURRENTLY EXISTS FOR TESTING
-    fn perform_one_operation(&mut self) {
+    pub fn perform_one_operation(&mut self) {
+        if self.stopped {
+            self.wait_for_joypad();
+            return;
+        }
+
+        let cycles_before = self.clock.m_cycles();
+
+        if self.halted {
+            if self.memory.interrupts.pending() == 0 {
+                // Still asleep, but the rest of the board keeps running
+                self.clock.cycle_clock(1);
+                self.memory.step(1);
+                self.record_audio(AUDIO_RECORDING_CHUNK);
+                return;
+            }
+
+            // Wakes up either way, IME only decides whether the handler gets called
+            self.halted = false;
+        }
+
+        if !self.service_interrupt() {
+            self.registers.apply_scheduled_ime();
+            self.execute_next_instruction();
+        }
+
+        // Everything else on the board catches up with however long the instruction took
+        let elapsed_cycles = (self.clock.m_cycles() - cycles_before) as u32;
+        self.memory.step(elapsed_cycles);
+        self.record_audio(AUDIO_RECORDING_CHUNK);
+    }
+
+    // Runs whole instructions until at least a frame's worth of cycles has gone by
+    pub fn run_frame(&mut self) {
+        let target = self.clock.m_cycles() + M_CYCLES_PER_FRAME;
+        while self.clock.m_cycles() < target {
+            self.perform_one_operation();
+        }
+    }
+
+    // Everything but the clock is frozen in STOP, only a button press on a selected row gets us out
+    fn wait_for_joypad(&mut self) {
+        self.clock.cycle_clock(1);
+
+        if self.memory.joypad.lines() != 0x0F {
+            self.stopped = false;
+        }
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        self.memory.gpu.framebuffer()
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.memory.gpu.set_renderer(renderer);
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.memory.apu.set_sample_rate(sample_rate);
+    }
+
+    // Stereo samples generated since the last call, interleaved left/right
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.memory.apu.take_samples()
+    }
+
+    // Everything the apu produces from here on goes to a 16-bit stereo wav file. While
+    // recording, the samples don't come out of take_audio_samples.
+    pub fn start_audio_recording(&mut self, file_path: &str) -> io::Result<()> {
+        self.stop_audio_recording()?;
+
+        let file = BufWriter::new(File::create(file_path)?);
+        self.audio_recorder = Some(WavWriter::new(file, self.memory.apu.sample_rate())?);
+        // Nothing from before the start
+        self.memory.apu.take_samples();
+        Ok(())
+    }
+
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        self.record_audio(0);
+
+        if let Some(error) = self.audio_recorder_error.take() {
+            return Err(error);
+        }
+        if let Some(recorder) = self.audio_recorder.take() {
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_recording_audio(&self) -> bool {
+        self.audio_recorder.is_some()
+    }
+
+    fn record_audio(&mut self, min_samples: usize) {
+        let Some(recorder) = &mut self.audio_recorder else {
+            return;
+        };
+        if self.memory.apu.buffered_samples() < min_samples.max(1) {
+            return;
+        }
+
+        if let Err(error) = recorder.write_samples(&self.memory.apu.take_samples()) {
+            // Patch the sizes in anyway, so whatever made it out is still a playable file
+            if let Some(recorder) = self.audio_recorder.take() {
+                let _ = recorder.finish();
+            }
+            self.audio_recorder_error = Some(error);
+        }
+    }
+
+    // Writes a Gameboy Doctor line to file_path before every instruction from here on.
+    // See https://github.com/robert/gameboy-doctor
+    pub fn start_trace(&mut self, file_path: &str) -> io::Result<()> {
+        self.stop_trace()?;
+        self.trace = Some(BufWriter::new(File::create(file_path)?));
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        if let Some(error) = self.trace_error.take() {
+            return Err(error);
+        }
+        if let Some(mut trace) = self.trace.take() {
+            trace.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+    pub fn doctor_line(&self) -> String {
+        let registers = &self.registers;
+        let pc = registers.read_word(&RegWord::PC);
+        let pc_memory: Vec<String> = (0..4)
+            .map(|offset| format!("{:02X}", self.memory.peek_byte(pc.wrapping_add(offset))))
+            .collect();
+
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+            registers.read_byte(&RegByte::A),
+            registers.read_byte(&RegByte::F),
+            registers.read_byte(&RegByte::B),
+            registers.read_byte(&RegByte::C),
+            registers.read_byte(&RegByte::D),
+            registers.read_byte(&RegByte::E),
+            registers.read_byte(&RegByte::H),
+            registers.read_byte(&RegByte::L),
+            registers.read_word(&RegWord::SP),
+            pc,
+            pc_memory.join(",")
+        )
+    }
+
+    fn trace_instruction(&mut self) {
+        if self.trace.is_none() {
+            return;
+        }
+
+        let line = self.doctor_line();
+        if let Some(trace) = &mut self.trace
+            && let Err(error) = writeln!(trace, "{}", line)
+        {
+            self.trace = None;
+            self.trace_error = Some(error);
+        }
+    }
+
+    // Plug something into the link port, disconnected by default
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.memory.serial.set_link(link);
+    }
+
+    pub fn serial_output(&self) -> &[u8] {
+        self.memory.serial.link().captured()
+    }
+
+    // Input from whatever frontend is driving us
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.memory
+            .joypad
+            .set_button(button, pressed, &mut self.memory.interrupts);
+    }
+
+    // Checked before every instruction fetch. Jumping to the handler replaces the fetch entirely.
+    fn service_interrupt(&mut self) -> bool {
+        if !self.registers.read_ime() {
+            return false;
+        }
+
+        let Some(interrupt) = self.memory.interrupts.highest_priority_pending() else {
+            return false;
+        };
+
+        self.registers.write_ime(false);
+        self.memory.interrupts.clear(interrupt);
+
+        let [msb, lsb] = self.registers.read_word(&RegWord::PC).to_be_bytes();
+        load_byte_into_stack_after_decrement_stack_pointer(self, msb);
+        load_byte_into_stack_after_decrement_stack_pointer(self, lsb);
+        self.registers.write_word(&RegWord::PC, interrupt.vector());
+
+        self.clock.cycle_clock(5);
+        true
+    }
+
+    fn execute_next_instruction(&mut self) {
+        self.trace_instruction();
+
         let instruction = self.fetch_next_byte();
         let instruction_length = Motherboard::get_instruction_length(instruction);
 
@@ -420,7 +705,7 @@ impl Motherboard {
                 return;
             }
             _ => panic!(
-                "ERROR::Testing perform_one_operation failed. Instruction length was neither 1, 2, or 3."
+                "ERROR::Testing execute_next_instruction failed. Instruction length was neither 1, 2, or 3."
             ),
         }
     }
@@ -430,9 +715,24 @@ impl Motherboard {
     }
 }
 
+// Best effort so a recording that never got stopped still has its sizes filled in,
+// stop_audio_recording is the way to find out whether that worked
+impl Drop for Motherboard {
+    fn drop(&mut self) {
+        let _ = self.stop_audio_recording();
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{cpu_logic::load_byte_to_virtual_register_target, motherboard, registers::RegByte};
+    use crate::{
+        cartridge::{build_test_rom, test_path},
+        cpu_logic::load_byte_to_virtual_register_target,
+        interrupts::Interrupt,
+        motherboard,
+        registers::RegByte,
+        serial::CaptureLink,
+    };
 
     use super::*;
 
@@ -441,29 +741,439 @@ mod tests {
     fn chain_commands_using_funcs_all_files() {
         let mut motherboard = Motherboard::new();
 
-        // PC is at the 21st memory address/instruction
-        motherboard.registers.write_word(&RegWord::PC, 0x20);
-        // At 22nd memory instruction lives the opcode for loading A to B
-        motherboard.memory.write_byte(0x21, 0x47);
-        // At the 21st memory instruction lives the opcode for incrementing A
-        motherboard.memory.write_byte(0x20, 0x3C);
+        // PC is at the 21st byte of work ram (rom ignores writes, so the program lives in wram)
+        motherboard.registers.write_word(&RegWord::PC, 0xC020);
+        // At 22nd byte lives the opcode for loading A to B
+        motherboard.memory.write_byte(0xC021, 0x47);
+        // At the 21st byte lives the opcode for incrementing A
+        motherboard.memory.write_byte(0xC020, 0x3C);
 
         motherboard.registers.pretty_print_word();
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x20);
-        assert_eq!(motherboard.memory.read_byte(0x20), 0x3C);
-        assert_eq!(motherboard.memory.read_byte(0x21), 0x47);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC020);
+        assert_eq!(motherboard.memory.read_byte(0xC020), 0x3C);
+        assert_eq!(motherboard.memory.read_byte(0xC021), 0x47);
 
         motherboard.perform_one_operation();
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x21);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC021);
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
         assert_eq!(motherboard.registers.read_byte(&RegByte::B), 0x00);
 
         motherboard.perform_one_operation();
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x22);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC022);
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
         assert_eq!(motherboard.registers.read_byte(&RegByte::B), 0x01);
     }
+
+    #[test]
+    fn interrupt_dispatch_pushes_pc_and_jumps_to_vector() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFFE);
+        motherboard.registers.write_ime(true);
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0101);
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0100);
+
+        motherboard.perform_one_operation();
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0050);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xDFFC);
+        assert_eq!(motherboard.memory.read_byte(0xDFFD), 0xC1);
+        assert_eq!(motherboard.memory.read_byte(0xDFFC), 0x00);
+        assert_eq!(motherboard.memory.read_byte(0xFF0F), 0xE0);
+        assert!(!motherboard.registers.read_ime());
+        assert_eq!(motherboard.clock.m_cycles(), 5);
+    }
+
+    #[test]
+    fn interrupt_not_dispatched_without_ime() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x00); // NOP
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0001);
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0001);
+
+        motherboard.perform_one_operation();
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC101);
+        assert_eq!(motherboard.memory.read_byte(0xFF0F), 0xE1);
+    }
+
+    #[test]
+    fn ei_takes_effect_after_next_instruction() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFFE);
+        motherboard.memory.write_byte(0xC100, 0xFB); // EI
+        motherboard.memory.write_byte(0xC101, 0x3C); // INC A
+        motherboard.memory.write_byte(0xC102, 0x3C); // INC A
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0001);
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0001);
+
+        motherboard.perform_one_operation();
+        assert!(!motherboard.registers.read_ime());
+
+        // The instruction right after EI still runs before the interrupt is taken
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC102);
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0040);
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
+    }
+
+    #[test]
+    fn labels_follow_the_mapped_bank() {
+        let rom = build_test_rom(0x01, 0x01, 0x00); // 64 KiB MBC1
+
+        let mut motherboard = Motherboard::new();
+        motherboard.memory.cartridge = Cartridge::from_bytes(rom).unwrap();
+        motherboard.symbols =
+            Symbols::parse("00:0150 Start\n01:4000 BankOne\n02:4000 BankTwo\n00:c000 wRam\n")
+                .unwrap();
+
+        assert_eq!(motherboard.label_at(0x0150), Some("Start"));
+        assert_eq!(motherboard.label_at(0x4000), Some("BankOne"));
+        assert_eq!(motherboard.label_at(0xC000), Some("wRam"));
+
+        motherboard.memory.write_byte(0x2000, 0x02);
+        assert_eq!(motherboard.label_at(0x4000), Some("BankTwo"));
+        motherboard.memory.write_byte(0x2000, 0x03);
+        assert_eq!(motherboard.label_at(0x4000), None);
+    }
+
+    #[test]
+    fn pop_de_and_ret_nc_opcodes() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFF0);
+        motherboard.registers.write_word(&RegWord::AF, 0x0000);
+        for (offset, byte) in [0x34, 0x12, 0x00, 0xC2].iter().enumerate() {
+            motherboard.memory.write_byte(0xDFF0 + offset as u16, *byte);
+        }
+        motherboard.memory.write_byte(0xC100, 0xD1); // POP DE
+        motherboard.memory.write_byte(0xC101, 0xD0); // RET NC
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::DE), 0x1234);
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC200);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xDFF4);
+    }
+
+    #[test]
+    fn ld_c_contents_is_one_byte() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_byte(&RegByte::A, 0x42);
+        motherboard.registers.write_byte(&RegByte::C, 0x80);
+        motherboard.memory.write_byte(0xC100, 0xE2); // LD (C),A
+        motherboard.memory.write_byte(0xC101, 0x3C); // INC A
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.memory.read_byte(0xFF80), 0x42);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC101);
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x43);
+    }
+
+    #[test]
+    fn halt_sleeps_until_interrupt_pending() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x76); // HALT
+        motherboard.memory.write_byte(0xC101, 0x3C); // INC A
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0100);
+
+        motherboard.perform_one_operation();
+        assert!(motherboard.halted);
+
+        motherboard.perform_one_operation();
+        motherboard.perform_one_operation();
+        assert!(motherboard.halted);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC101);
+
+        // IME is off, so it wakes up and carries on without calling the handler
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0100);
+        motherboard.perform_one_operation();
+        assert!(!motherboard.halted);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC102);
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
+    }
+
+    #[test]
+    fn halt_wakes_into_interrupt_handler_with_ime() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_word(&RegWord::SP, 0xDFFE);
+        motherboard.registers.write_ime(true);
+        motherboard.memory.write_byte(0xC100, 0x76); // HALT
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0001);
+
+        motherboard.perform_one_operation();
+        assert!(motherboard.halted);
+
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0001);
+        motherboard.perform_one_operation();
+
+        assert!(!motherboard.halted);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0040);
+        assert_eq!(motherboard.memory.read_byte(0xDFFC), 0x01);
+    }
+
+    #[test]
+    fn halt_bug_reads_next_byte_twice() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x76); // HALT
+        motherboard.memory.write_byte(0xC101, 0x3C); // INC A
+        motherboard.memory.write_byte(0xFFFF, 0b0000_0001);
+        motherboard.memory.write_byte(0xFF0F, 0b0000_0001);
+
+        motherboard.perform_one_operation();
+        assert!(!motherboard.halted);
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC101);
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC102);
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x02);
+    }
+
+    #[test]
+    fn stop_waits_for_joypad_and_resets_div() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x10); // STOP
+        motherboard.memory.write_byte(0xC101, 0x00);
+        motherboard.memory.write_byte(0xC102, 0x3C); // INC A
+
+        motherboard.perform_one_operation();
+        assert!(motherboard.stopped);
+        assert_eq!(motherboard.memory.read_byte(0xFF04), 0x00);
+
+        motherboard.perform_one_operation();
+        assert!(motherboard.stopped);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC102);
+
+        motherboard.set_button(Button::Start, true);
+        assert!(motherboard.memory.interrupts.read_flags() & Interrupt::Joypad.mask() > 0);
+        motherboard.perform_one_operation();
+        assert!(!motherboard.stopped);
+
+        motherboard.perform_one_operation();
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0x01);
+    }
+
+    #[test]
+    fn serial_transfer_through_link() {
+        let mut motherboard = Motherboard::new();
+        motherboard.set_serial_link(Box::new(CaptureLink::new()));
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.registers.write_ime(true);
+        motherboard.memory.write_byte(0xFFFF, 0b0000_1000);
+        motherboard.memory.write_byte(0xFF01, b'A');
+        motherboard.memory.write_byte(0xFF02, 0x81);
+
+        // JR -2 until the byte is shifted out
+        motherboard.memory.write_byte(0xC100, 0x18);
+        motherboard.memory.write_byte(0xC101, 0xFE);
+        while motherboard.registers.read_word(&RegWord::PC) != 0x0058 {
+            motherboard.perform_one_operation();
+        }
+
+        assert_eq!(motherboard.serial_output(), b"A");
+        assert_eq!(motherboard.memory.read_byte(0xFF01), 0xFF);
+    }
+
+    #[test]
+    fn polling_ly_for_vblank_terminates() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xFF40, 0x80);
+
+        // LDH A,(0x44); CP 0x90; JR NZ,-6
+        for (offset, byte) in [0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA].iter().enumerate() {
+            motherboard.memory.write_byte(0xC100 + offset as u16, *byte);
+        }
+
+        for _ in 0..100_000 {
+            if motherboard.registers.read_word(&RegWord::PC) == 0xC106 {
+                break;
+            }
+            motherboard.perform_one_operation();
+        }
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC106);
+        assert_eq!(motherboard.memory.read_byte(0xFF44), 0x90);
+    }
+
+    #[test]
+    fn boot_rom_runs_then_hands_over() {
+        let mut motherboard = Motherboard::new();
+        let rom_path = test_path("boot_rom_runs_then_hands_over.gb");
+        let boot_rom_path = test_path("boot_rom_runs_then_hands_over.bin");
+        std::fs::write(&rom_path, build_test_rom(0x00, 0x00, 0x00)).unwrap();
+
+        // LD A,0x01; LDH (0x50),A; then NOPs up to 0x0100
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        std::fs::write(&boot_rom_path, boot_rom).unwrap();
+
+        motherboard
+            .load_boot_rom_file(boot_rom_path.to_str().unwrap())
+            .unwrap();
+        motherboard
+            .load_rom_file(rom_path.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&rom_path).unwrap();
+        std::fs::remove_file(&boot_rom_path).unwrap();
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0000);
+        assert_eq!(motherboard.memory.read_byte(0x0000), 0x3E);
+
+        motherboard.perform_one_operation();
+        motherboard.perform_one_operation();
+        assert!(!motherboard.memory.boot_rom_mapped());
+        assert_eq!(motherboard.memory.read_byte(0x0000), 0x00);
+    }
+
+    #[test]
+    fn no_boot_rom_starts_post_boot() {
+        let mut motherboard = Motherboard::new();
+        let rom_path = test_path("no_boot_rom_starts_post_boot.gb");
+        std::fs::write(&rom_path, build_test_rom(0x00, 0x00, 0x00)).unwrap();
+
+        motherboard
+            .load_rom_file(rom_path.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        std::fs::remove_file(&rom_path).unwrap();
+
+        assert_eq!(motherboard.registers.read_word(&RegWord::AF), 0x01B0);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xFFFE);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0100);
+        assert_eq!(motherboard.memory.read_byte(0xFF40), 0x91);
+    }
+
+    #[test]
+    fn failed_save_write_still_loads_the_next_rom() {
+        let directory = test_path("save_error_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let battery_rom_path = directory.join("battery.gb");
+        let rom_path = directory.join("plain.gb");
+        // MBC1+RAM+BATTERY
+        std::fs::write(&battery_rom_path, build_test_rom(0x03, 0x00, 0x02)).unwrap();
+        std::fs::write(&rom_path, build_test_rom(0x00, 0x00, 0x00)).unwrap();
+        // A directory in place of the temp file makes the save write fail
+        std::fs::create_dir_all(directory.join("battery.sav.tmp")).unwrap();
+
+        let mut motherboard = Motherboard::new();
+        motherboard
+            .load_rom_file(battery_rom_path.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        let save_result = motherboard
+            .load_rom_file(rom_path.to_str().unwrap())
+            .unwrap();
+
+        assert!(save_result.is_err());
+        assert_eq!(motherboard.memory.cartridge.save_path(), None);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0100);
+
+        drop(motherboard);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn records_audio_to_wav() {
+        let mut motherboard = Motherboard::new();
+        motherboard.set_audio_sample_rate(32_768);
+        // JR -2 while square 2 plays
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x18);
+        motherboard.memory.write_byte(0xC101, 0xFE);
+        motherboard.memory.write_byte(0xFF26, 0x80);
+        motherboard.memory.write_byte(0xFF24, 0x77);
+        motherboard.memory.write_byte(0xFF25, 0xFF);
+        motherboard.memory.write_byte(0xFF17, 0xF0);
+        motherboard.memory.write_byte(0xFF19, 0x87);
+
+        let path = test_path("records_audio_to_wav.wav");
+        let path = path.to_str().unwrap();
+        motherboard.start_audio_recording(path).unwrap();
+        assert!(motherboard.is_recording_audio());
+
+        let cycles_before = motherboard.clock.m_cycles();
+        motherboard.run_frame();
+        motherboard.run_frame();
+        let t_cycles = (motherboard.clock.m_cycles() - cycles_before) * 4;
+        motherboard.stop_audio_recording().unwrap();
+        assert!(!motherboard.is_recording_audio());
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // 32768 Hz is one sample every 128 t-cycles, 4 bytes each
+        let data_length = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        assert_eq!(data_length as u64, t_cycles / 128 * 4);
+        assert_eq!(bytes.len(), 44 + data_length as usize);
+        assert!(bytes[44..].iter().any(|byte| *byte != 0));
+        assert!(motherboard.take_audio_samples().is_empty());
+    }
+
+    #[test]
+    fn dropping_finishes_the_recording() {
+        let path = test_path("dropping_finishes_the_recording.wav");
+        let path = path.to_str().unwrap();
+
+        let mut motherboard = Motherboard::new();
+        motherboard.start_audio_recording(path).unwrap();
+        motherboard.run_frame();
+        drop(motherboard);
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let data_length = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        assert!(data_length > 0);
+        assert_eq!(bytes.len(), 44 + data_length as usize);
+    }
+
+    #[test]
+    fn traces_gameboy_doctor_lines() {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers = Registers::post_boot();
+        // INC A; JR -3
+        motherboard.registers.write_word(&RegWord::PC, 0xC100);
+        motherboard.memory.write_byte(0xC100, 0x3C);
+        motherboard.memory.write_byte(0xC101, 0x18);
+        motherboard.memory.write_byte(0xC102, 0xFD);
+
+        let path = test_path("traces_gameboy_doctor_lines.log");
+        let path = path.to_str().unwrap();
+        motherboard.start_trace(path).unwrap();
+        assert!(motherboard.is_tracing());
+
+        motherboard.perform_one_operation();
+        motherboard.perform_one_operation();
+        motherboard.stop_trace().unwrap();
+        assert!(!motherboard.is_tracing());
+        motherboard.perform_one_operation();
+
+        let trace = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            trace,
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C100 PCMEM:3C,18,FD,00\n\
+             A:02 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C101 PCMEM:18,FD,00,00\n"
+        );
+    }
 }
@@ -5,6 +5,11 @@ use std::convert::From;
 
 // Load = LD, load right value into left, aka LD_B_C == Load C into B
 
+// Nothing is behind these, OneByteOpCode::from panics on them
+pub const UNUSED_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
 impl From<u8> for OneByteOpCode {
     fn from(code: u8) -> Self {
         match code {
@@ -202,8 +207,8 @@ impl From<u8> for OneByteOpCode {
             0xC9 => OneByteOpCode::RET,
             0xCF => OneByteOpCode::RST_08H,
             // Dx
-            0xD1 => OneByteOpCode::RET_NC,
-            0xD2 => OneByteOpCode::POP_DE,
+            0xD0 => OneByteOpCode::RET_NC,
+            0xD1 => OneByteOpCode::POP_DE,
             0xD5 => OneByteOpCode::PUSH_DE,
             0xD7 => OneByteOpCode::RST_10H,
             0xD8 => OneByteOpCode::RET_C,
@@ -440,8 +445,8 @@ pub enum OneByteOpCode {
     RST_08H = 0xCF,
 
     // Dx
-    RET_NC = 0xD1,
-    POP_DE = 0xD2,
+    RET_NC = 0xD0,
+    POP_DE = 0xD1,
     PUSH_DE = 0xD5,
     RST_10H = 0xD7,
     RET_C = 0xD8,
@@ -467,10 +467,8 @@ mod tests {
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::LD_HLincrementedcontents_A);
 
         assert_eq!(motherboard.registers.read_word(&RegWord::HL), 0b1);
-        assert_eq!(
-            motherboard.memory.read_byte(0b0),
-            motherboard.registers.read_byte(&RegByte::A)
-        );
+        // HL wrapped around to 0x0000, which is rom and ignores the write
+        assert_eq!(motherboard.memory.read_byte(0b0), 0b0);
     }
 
     // Loading from virtual register to 8-bit register
@@ -612,10 +610,10 @@ mod tests {
 
         motherboard
             .registers
-            .write_word(&RegWord::BC, 0b0100_1001_0000_1111);
+            .write_word(&RegWord::BC, 0b1100_1001_0000_1111);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
 
         motherboard
@@ -633,7 +631,7 @@ mod tests {
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0b0000_0010);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
         assert_eq!(
             motherboard
@@ -642,7 +640,7 @@ mod tests {
             0b0000_0010
         );
         assert_eq!(
-            motherboard.memory.read_byte(0b0100_1001_0000_1111),
+            motherboard.memory.read_byte(0b1100_1001_0000_1111),
             0b0000_0010
         );
     }
@@ -662,10 +660,10 @@ mod tests {
 
         motherboard
             .registers
-            .write_word(&RegWord::BC, 0b0100_1001_0000_1111);
+            .write_word(&RegWord::BC, 0b1100_1001_0000_1111);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
 
         motherboard
@@ -685,7 +683,7 @@ mod tests {
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0b1000_0001);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
         assert_eq!(
             motherboard
@@ -694,7 +692,7 @@ mod tests {
             0b1000_0001
         );
         assert_eq!(
-            motherboard.memory.read_byte(0b0100_1001_0000_1111),
+            motherboard.memory.read_byte(0b1100_1001_0000_1111),
             0b1000_0001
         );
     }
@@ -981,21 +979,21 @@ mod tests {
         let mut motherboard = motherboard::Motherboard::new();
 
         // Setup PC
-        motherboard.registers.write_word(&RegWord::PC, 0x0010);
-        motherboard.memory.write_byte(0x0010, 0x88);
+        motherboard.registers.write_word(&RegWord::PC, 0xC010);
+        motherboard.memory.write_byte(0xC010, 0x88);
 
         motherboard.memory.write_byte(0xAA21, 0x07);
 
         // Setup stack
-        motherboard.registers.write_word(&RegWord::SP, 0x4000);
+        motherboard.registers.write_word(&RegWord::SP, 0xD000);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x3FFF, 0xAA);
+        motherboard.memory.write_byte(0xCFFF, 0xAA);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x3FFE, 0x21);
+        motherboard.memory.write_byte(0xCFFE, 0x21);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::RET_NZ);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0x4000);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xD000);
         assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xAA21);
         assert_eq!(
             motherboard
@@ -1012,22 +1010,22 @@ mod tests {
         motherboard.registers.write_flag(RegFlag::Zero, true);
 
         // Setup PC
-        motherboard.registers.write_word(&RegWord::PC, 0x0010);
-        motherboard.memory.write_byte(0x0010, 0x88);
+        motherboard.registers.write_word(&RegWord::PC, 0xC010);
+        motherboard.memory.write_byte(0xC010, 0x88);
 
         motherboard.memory.write_byte(0xAA21, 0x07);
 
         // Setup stack
-        motherboard.registers.write_word(&RegWord::SP, 0x4000);
+        motherboard.registers.write_word(&RegWord::SP, 0xD000);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x3FFF, 0xAA);
+        motherboard.memory.write_byte(0xCFFF, 0xAA);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x3FFE, 0x21);
+        motherboard.memory.write_byte(0xCFFE, 0x21);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::RET_NZ);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0x3FFE);
-        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0010);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xCFFE);
+        assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xC010);
         assert_eq!(
             motherboard
                 .memory
@@ -1069,11 +1067,12 @@ mod tests {
                 .read_byte(motherboard.registers.read_word(&RegWord::PC)),
             0x12
         );
+        // SP wrapped back around into rom, which ignored the write of 0x21
         assert_eq!(
             motherboard
                 .memory
                 .read_byte(motherboard.registers.read_word(&RegWord::SP)),
-            0x21
+            0x00
         );
     }
 
@@ -1115,25 +1114,25 @@ mod tests {
         assert_eq!(motherboard.registers.read_byte(&RegByte::C), 0xFF);
 
         // Setup stack
-        motherboard.registers.write_word(&RegWord::SP, 0x1F00);
-        motherboard.memory.write_byte(0x1F00, 0x21);
+        motherboard.registers.write_word(&RegWord::SP, 0xDF00);
+        motherboard.memory.write_byte(0xDF00, 0x21);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x1EFF, 0x45);
+        motherboard.memory.write_byte(0xDEFF, 0x45);
         motherboard.registers.decrement_sp();
-        motherboard.memory.write_byte(0x1EFE, 0xBB);
+        motherboard.memory.write_byte(0xDEFE, 0xBB);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::PUSH_BC);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0x1EFC);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xDEFC);
         assert_eq!(motherboard.registers.read_word(&RegWord::BC), 0x2AFF);
         assert_eq!(motherboard.registers.read_byte(&RegByte::B), 0x2A);
         assert_eq!(motherboard.registers.read_byte(&RegByte::C), 0xFF);
 
-        assert_eq!(motherboard.memory.read_byte(0x1F00), 0x21);
-        assert_eq!(motherboard.memory.read_byte(0x1EFF), 0x45);
-        assert_eq!(motherboard.memory.read_byte(0x1EFE), 0xBB);
-        assert_eq!(motherboard.memory.read_byte(0x1EFD), 0x2A);
-        assert_eq!(motherboard.memory.read_byte(0x1EFC), 0xFF);
+        assert_eq!(motherboard.memory.read_byte(0xDF00), 0x21);
+        assert_eq!(motherboard.memory.read_byte(0xDEFF), 0x45);
+        assert_eq!(motherboard.memory.read_byte(0xDEFE), 0xBB);
+        assert_eq!(motherboard.memory.read_byte(0xDEFD), 0x2A);
+        assert_eq!(motherboard.memory.read_byte(0xDEFC), 0xFF);
     }
 
     #[test]
@@ -1159,8 +1158,9 @@ mod tests {
         assert_eq!(motherboard.registers.read_byte(&RegByte::B), 0xBB);
         assert_eq!(motherboard.registers.read_byte(&RegByte::C), 0x19);
 
-        assert_eq!(motherboard.memory.read_byte(0x0001), 0x22);
-        assert_eq!(motherboard.memory.read_byte(0x0000), 0xBB);
+        // 0x0000-0x0001 are rom, so the writes that wrapped around the stack don't stick
+        assert_eq!(motherboard.memory.read_byte(0x0001), 0x00);
+        assert_eq!(motherboard.memory.read_byte(0x0000), 0x00);
         assert_eq!(motherboard.memory.read_byte(0xFFFF), 0x19);
     }
 
@@ -1172,7 +1172,7 @@ mod tests {
         motherboard.registers.write_word(&RegWord::PC, 0xABCD);
 
         // Setup stack
-        motherboard.registers.write_word(&RegWord::SP, 0x1000);
+        motherboard.registers.write_word(&RegWord::SP, 0xD000);
         motherboard
             .memory
             .write_byte(motherboard.registers.read_word(&RegWord::SP), 0x99);
@@ -1181,14 +1181,13 @@ mod tests {
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::RST_08H);
         motherboard.registers.pretty_print_word();
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0x0FFE);
+        assert_eq!(motherboard.registers.read_word(&RegWord::SP), 0xCFFE);
         assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0x0008);
 
-        assert_eq!(motherboard.memory.read_byte(0x1000), 0x99);
-        assert_eq!(motherboard.memory.read_byte(0x0FFF), 0xAB);
-        // 0xCD becomes 0xCE because it's the low byte and we have to increment program counter once
-        // To push the address of the byte AFTER THIS OneByteOpCode unto the stack.
-        assert_eq!(motherboard.memory.read_byte(0x0FFE), 0xCE);
+        assert_eq!(motherboard.memory.read_byte(0xD000), 0x99);
+        assert_eq!(motherboard.memory.read_byte(0xCFFF), 0xAB);
+        // PC is already past the RST by the time it executes, so it gets pushed as is
+        assert_eq!(motherboard.memory.read_byte(0xCFFE), 0xCD);
     }
 
     // OLD TESTS CONVERTED TO NEW:
@@ -1964,31 +1963,31 @@ mod tests {
         motherboard.registers.write_flag(RegFlag::HalfCarry, true);
         motherboard.registers.write_flag(RegFlag::Carry, true);
 
-        motherboard.registers.write_word(&RegWord::HL, 9000);
+        motherboard.registers.write_word(&RegWord::HL, 50000);
         motherboard
             .memory
             .write_byte(motherboard.registers.read_word(&RegWord::HL), 49);
-        motherboard.memory.write_byte(8999, 200);
+        motherboard.memory.write_byte(49999, 200);
         motherboard.registers.write_byte(&RegByte::A, 10);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::LD_A_HLdecrementedcontents);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::HL), 8999);
-        assert_eq!(motherboard.memory.read_byte(9000), 49);
+        assert_eq!(motherboard.registers.read_word(&RegWord::HL), 49999);
+        assert_eq!(motherboard.memory.read_byte(50000), 49);
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 49);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::LD_A_HLincrementedcontents);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::HL), 9000);
-        assert_eq!(motherboard.memory.read_byte(8999), 200);
+        assert_eq!(motherboard.registers.read_word(&RegWord::HL), 50000);
+        assert_eq!(motherboard.memory.read_byte(49999), 200);
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 200);
 
-        motherboard.registers.write_word(&RegWord::BC, 4000);
-        motherboard.memory.write_byte(4000, 1);
+        motherboard.registers.write_word(&RegWord::BC, 52000);
+        motherboard.memory.write_byte(52000, 1);
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::LD_A_BCcontents);
 
-        assert_eq!(motherboard.registers.read_word(&RegWord::BC), 4000);
+        assert_eq!(motherboard.registers.read_word(&RegWord::BC), 52000);
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 1);
 
         motherboard.registers.write_flag(RegFlag::Zero, true);
@@ -2083,11 +2082,12 @@ mod tests {
         );
 
         assert_eq!(motherboard.registers.read_word(&RegWord::BC), 2001);
+        // BC points into rom, which ignores writes
         assert_eq!(
             motherboard
                 .memory
                 .read_byte(motherboard.registers.read_word(&RegWord::BC)),
-            25
+            0
         );
 
         // Where HL should point to after ADD_HL_BC
@@ -2103,11 +2103,12 @@ mod tests {
                 .read_byte(motherboard.registers.read_word(&RegWord::HL)),
             99
         );
+        // BC points into rom, which ignores writes
         assert_eq!(
             motherboard
                 .memory
                 .read_byte(motherboard.registers.read_word(&RegWord::BC)),
-            25
+            0
         );
 
         execute_one_byte_opcode(&mut motherboard, OneByteOpCode::INC_HL);
@@ -2274,10 +2275,10 @@ mod tests {
 
         motherboard
             .registers
-            .write_word(&RegWord::BC, 0b0100_1001_0000_1111);
+            .write_word(&RegWord::BC, 0b1100_1001_0000_1111);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
 
         motherboard
@@ -2295,7 +2296,7 @@ mod tests {
         assert_eq!(motherboard.registers.read_byte(&RegByte::A), 0b1000_0001);
         assert_eq!(
             motherboard.registers.read_word(&RegWord::BC),
-            0b0100_1001_0000_1111
+            0b1100_1001_0000_1111
         );
         assert_eq!(
             motherboard
@@ -2304,7 +2305,7 @@ mod tests {
             0b1000_0001
         );
         assert_eq!(
-            motherboard.memory.read_byte(0b0100_1001_0000_1111),
+            motherboard.memory.read_byte(0b1100_1001_0000_1111),
             0b1000_0001
         );
     }
@@ -2591,12 +2592,12 @@ mod tests {
         execute_three_byte_opcode(
             &mut motherboard,
             ThreeByteOpCode::LD_A16contents_SP,
-            0xFF,
+            0xC0,
             0x10,
         );
 
-        assert_eq!(motherboard.memory.read_byte(0xFF10), 0xCD);
-        assert_eq!(motherboard.memory.read_byte(0xFF11), 0xAB);
+        assert_eq!(motherboard.memory.read_byte(0xC010), 0xCD);
+        assert_eq!(motherboard.memory.read_byte(0xC011), 0xAB);
     }
 
     // Loading a 16 bit num into SP
@@ -2651,7 +2652,7 @@ mod tests {
 
         assert_eq!(motherboard.registers.read_word(&RegWord::PC), 0xFA10);
         assert_eq!(motherboard.memory.read_byte(0xFFF4), 0x00);
-        assert_eq!(motherboard.memory.read_byte(0xFFF3), 0x11);
+        assert_eq!(motherboard.memory.read_byte(0xFFF3), 0x10);
     }
 
     // Prefix Tests Section
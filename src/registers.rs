@@ -38,11 +38,13 @@ pub struct Registers {
     sp: u16,
     pc: u16,
     ime: bool,
+    // Set by EI, IME only turns on after the instruction following it
+    ime_scheduled: bool,
 }
 
 impl Registers {
+    // Power-on state, which is what the boot rom starts from. See post_boot for skipping it.
     pub fn new() -> Self {
-        // TODO check if these should be initialized to certain values
         Self {
             a: 0x00,
             b: 0x00,
@@ -52,14 +54,31 @@ impl Registers {
             f: 0x00,
             h: 0x00,
             l: 0x00,
-            // TODO: Stack pointer should be set to end of memory (or at least
-            // > near the very end, the stack grows downwards away from the
-            // > end of memory towards the beginning)
             sp: 0x0000,
             pc: 0x0000,
             // TODO: Check if IME turns off all interrupts, or simply disables read/writing to them ->
             // E.g. a turned on interrupt register would remain turned on
             ime: false,
+            ime_scheduled: false,
+        }
+    }
+
+    // DMG state right after the boot rom hands over to the cartridge at 0x0100
+    // (https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers)
+    pub fn post_boot() -> Self {
+        Self {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+            ime: false,
+            ime_scheduled: false,
         }
     }
 
@@ -153,6 +172,18 @@ impl Registers {
         self.ime = bool;
     }
 
+    pub fn schedule_ime(&mut self) {
+        self.ime_scheduled = true;
+    }
+
+    // Called before each instruction, so an EI only takes effect once the next one has run
+    pub fn apply_scheduled_ime(&mut self) {
+        if self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+    }
+
     // Move to OpCode, rename to get_carry_and_update_flag
     pub fn add_carry(&mut self) -> u8 {
         let carry = self.read_flag(RegFlag::Carry);
@@ -183,6 +214,32 @@ impl Registers {
         self.pc = self.pc.wrapping_sub(1);
     }
 
+    // One line per register pair plus the flags, e.g. for dumping state at the end of a run
+    pub fn dump(&self) -> String {
+        let flag = |register_flag: RegFlag, name: char| {
+            if self.read_flag(register_flag) {
+                name
+            } else {
+                '-'
+            }
+        };
+
+        format!(
+            "AF: {:04X}\nBC: {:04X}\nDE: {:04X}\nHL: {:04X}\nSP: {:04X}\nPC: {:04X}\nFlags: {}{}{}{} IME: {}",
+            self.read_word(&RegWord::AF),
+            self.read_word(&RegWord::BC),
+            self.read_word(&RegWord::DE),
+            self.read_word(&RegWord::HL),
+            self.sp,
+            self.pc,
+            flag(RegFlag::Zero, 'Z'),
+            flag(RegFlag::Subtraction, 'N'),
+            flag(RegFlag::HalfCarry, 'H'),
+            flag(RegFlag::Carry, 'C'),
+            self.ime as u8,
+        )
+    }
+
     // Register Word Printer for Debug
     pub fn pretty_print_word(&mut self) {
         println!(
@@ -383,4 +440,27 @@ mod tests {
         registers.increment_pc();
         assert_eq!(registers.pc, 0x01);
     }
+
+    #[test]
+    fn post_boot_registers() {
+        let mut registers = Registers::post_boot();
+
+        assert_eq!(registers.read_word(&RegWord::AF), 0x01B0);
+        assert_eq!(registers.read_word(&RegWord::BC), 0x0013);
+        assert_eq!(registers.read_word(&RegWord::DE), 0x00D8);
+        assert_eq!(registers.read_word(&RegWord::HL), 0x014D);
+        assert_eq!(registers.read_word(&RegWord::SP), 0xFFFE);
+        assert_eq!(registers.read_word(&RegWord::PC), 0x0100);
+        assert!(!registers.read_ime());
+    }
+
+    #[test]
+    fn dump_registers() {
+        let registers = Registers::post_boot();
+
+        assert_eq!(
+            registers.dump(),
+            "AF: 01B0\nBC: 0013\nDE: 00D8\nHL: 014D\nSP: FFFE\nPC: 0100\nFlags: Z-HC IME: 0"
+        );
+    }
 }
@@ -0,0 +1,119 @@
+use crate::debugger::Breakpoint;
+use crate::motherboard::{M_CYCLES_PER_FRAME, Motherboard};
+use crate::registers::RegWord;
+
+// Whichever is hit first ends the run. Frames are counted in cycles, 17556 m-cycles each.
+pub struct RunLimits {
+    pub frames: Option<u64>,
+    pub m_cycles: Option<u64>,
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    // Carries the frame count, the clock only counts m-cycles
+    FrameLimit(u64),
+    CycleLimit,
+}
+
+// Runs headlessly until a breakpoint is about to execute or the cycle budget is used up.
+// With no limits at all this only comes back on a breakpoint.
+pub fn run(motherboard: &mut Motherboard, limits: &RunLimits) -> StopReason {
+    // The tighter limit is the one reported, frames on a tie
+    let (budget, limit_reason) = match (limits.frames, limits.m_cycles) {
+        (Some(frames), Some(m_cycles)) if m_cycles < frames * M_CYCLES_PER_FRAME => {
+            (Some(m_cycles), StopReason::CycleLimit)
+        }
+        (Some(frames), _) => (
+            Some(frames * M_CYCLES_PER_FRAME),
+            StopReason::FrameLimit(frames),
+        ),
+        (None, m_cycles) => (m_cycles, StopReason::CycleLimit),
+    };
+    let end = budget.map(|budget| motherboard.clock.m_cycles() + budget);
+
+    loop {
+        let pc = motherboard.registers.read_word(&RegWord::PC);
+        if limits
+            .breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.is_hit(motherboard, pc))
+        {
+            return StopReason::Breakpoint(pc);
+        }
+        if end.is_some_and(|end| motherboard.clock.m_cycles() >= end) {
+            return limit_reason;
+        }
+
+        motherboard.perform_one_operation();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registers::RegByte;
+
+    use super::*;
+
+    // INC A; JR -3
+    fn looping_motherboard() -> Motherboard {
+        let mut motherboard = Motherboard::new();
+        motherboard.registers.write_word(&RegWord::PC, 0xC000);
+        motherboard.memory.write_byte(0xC000, 0x3C);
+        motherboard.memory.write_byte(0xC001, 0x18);
+        motherboard.memory.write_byte(0xC002, 0xFD);
+        motherboard
+    }
+
+    #[test]
+    fn stops_at_breakpoint() {
+        let mut motherboard = looping_motherboard();
+        let limits = RunLimits {
+            frames: Some(1),
+            m_cycles: None,
+            breakpoints: vec![Breakpoint {
+                address: 0xC001,
+                bank: None,
+            }],
+        };
+
+        assert_eq!(
+            run(&mut motherboard, &limits),
+            StopReason::Breakpoint(0xC001)
+        );
+        assert_eq!(motherboard.clock.m_cycles(), 1);
+    }
+
+    #[test]
+    fn stops_at_cycle_limit() {
+        let mut motherboard = looping_motherboard();
+        let limits = RunLimits {
+            frames: Some(1),
+            m_cycles: Some(100),
+            breakpoints: vec![Breakpoint {
+                address: 0xD000,
+                bank: None,
+            }],
+        };
+
+        // 4 m-cycles a loop
+        assert_eq!(run(&mut motherboard, &limits), StopReason::CycleLimit);
+        assert_eq!(motherboard.clock.m_cycles(), 100);
+        assert_eq!(motherboard.registers.read_byte(&RegByte::A), 25);
+    }
+
+    #[test]
+    fn frames_are_counted_in_cycles() {
+        let mut motherboard = looping_motherboard();
+        let limits = RunLimits {
+            frames: Some(2),
+            m_cycles: None,
+            breakpoints: Vec::new(),
+        };
+
+        assert_eq!(run(&mut motherboard, &limits), StopReason::FrameLimit(2));
+        assert!(motherboard.clock.m_cycles() >= 2 * M_CYCLES_PER_FRAME);
+        assert!(motherboard.clock.m_cycles() < 2 * M_CYCLES_PER_FRAME + 4);
+    }
+}
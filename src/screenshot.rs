@@ -0,0 +1,181 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Shade 0 is the lightest
+const GRAYS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Stored deflate blocks top out at 65535 bytes
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+// Picks the format from the extension, PNG unless it's .ppm
+pub fn save(path: &str, framebuffer: &[u8]) -> io::Result<()> {
+    let is_ppm = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ppm"));
+
+    let bytes = if is_ppm {
+        encode_ppm(framebuffer)
+    } else {
+        encode_png(framebuffer)
+    };
+    fs::write(path, bytes)
+}
+
+fn grays(framebuffer: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    framebuffer
+        .iter()
+        .map(|shade| GRAYS[(*shade & 0b11) as usize])
+}
+
+// Binary P6, gray written out as RGB
+pub fn encode_ppm(framebuffer: &[u8]) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT).into_bytes();
+    for gray in grays(framebuffer) {
+        bytes.extend_from_slice(&[gray, gray, gray]);
+    }
+    bytes
+}
+
+// 8-bit grayscale. The image data isn't compressed at all, zlib just wraps it in stored blocks.
+pub fn encode_png(framebuffer: &[u8]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&(SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(SCREEN_HEIGHT as u32).to_be_bytes());
+    // Bit depth 8, grayscale, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    // Every row starts with its filter type, always none
+    let mut raw = Vec::with_capacity((SCREEN_WIDTH + 1) * SCREEN_HEIGHT);
+    let grays: Vec<u8> = grays(framebuffer).collect();
+    for row in grays.chunks(SCREEN_WIDTH) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut bytes = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut bytes, b"IHDR", &header);
+    write_chunk(&mut bytes, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut bytes, b"IEND", &[]);
+    bytes
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    let crc = crc32(&bytes[start..]);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate, 32K window, no dictionary, fastest
+    let mut bytes = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        bytes.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        bytes.push(last as u8);
+        bytes.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        bytes.extend_from_slice(block);
+    }
+
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 > 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pattern() -> Vec<u8> {
+        (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|i| (i % 4) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn ppm_is_rgb_grays() {
+        let bytes = encode_ppm(&test_pattern());
+        let header = b"P6\n160 144\n255\n";
+
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 160 * 144 * 3);
+        assert_eq!(
+            &bytes[header.len()..header.len() + 12],
+            [
+                0xFF, 0xFF, 0xFF, 0xAA, 0xAA, 0xAA, 0x55, 0x55, 0x55, 0x00, 0x00, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn png_chunks_and_pixels() {
+        let bytes = encode_png(&test_pattern());
+        assert_eq!(bytes[..8], PNG_SIGNATURE);
+
+        // IHDR
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[16..24], [0, 0, 0, 160, 0, 0, 0, 144]);
+
+        let idat_length = u32::from_be_bytes(bytes[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[37..41], b"IDAT");
+        let zlib = &bytes[41..41 + idat_length];
+        let crc = u32::from_be_bytes(
+            bytes[41 + idat_length..45 + idat_length]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(crc, crc32(&bytes[37..41 + idat_length]));
+
+        // One final stored block, then the first row: filter byte and 4 shades
+        let raw_length = 161 * 144;
+        assert_eq!(&zlib[..3], [0x78, 0x01, 0x01]);
+        assert_eq!(u16::from_le_bytes([zlib[3], zlib[4]]) as usize, raw_length);
+        assert_eq!(&zlib[7..12], [0x00, 0xFF, 0xAA, 0x55, 0x00]);
+        let raw = &zlib[7..7 + raw_length];
+        let adler = u32::from_be_bytes(zlib[7 + raw_length..].try_into().unwrap());
+        assert_eq!(adler, adler32(raw));
+
+        assert_eq!(&bytes[bytes.len() - 12..bytes.len() - 4], b"\0\0\0\0IEND");
+    }
+}
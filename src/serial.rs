@@ -0,0 +1,233 @@
+use std::io::{self, Write};
+
+use crate::interrupts::{Interrupt, Interrupts};
+
+pub const SB_ADDRESS: u16 = 0xFF01;
+pub const SC_ADDRESS: u16 = 0xFF02;
+
+const SC_TRANSFER_ENABLE: u8 = 0b1000_0000;
+const SC_INTERNAL_CLOCK: u8 = 0b0000_0001;
+const SC_BITS: u8 = SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK;
+
+// Internal clock runs at 8192 Hz
+const T_CYCLES_PER_BIT: u32 = 512;
+
+// Whatever is plugged into the other end of the link cable. Gets the byte we're shifting out
+// and hands back the byte that gets shifted in.
+pub trait SerialLink {
+    fn transfer(&mut self, outgoing: u8) -> u8;
+
+    // Everything sent so far, for backends that keep it around
+    fn captured(&self) -> &[u8] {
+        &[]
+    }
+}
+
+// No cable, the line floats high
+pub struct DisconnectedLink;
+
+impl SerialLink for DisconnectedLink {
+    fn transfer(&mut self, _outgoing: u8) -> u8 {
+        0xFF
+    }
+}
+
+// Keeps every byte sent, optionally echoing it to stdout. Test roms print their results this way.
+pub struct CaptureLink {
+    buffer: Vec<u8>,
+    echo_to_stdout: bool,
+}
+
+impl CaptureLink {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            echo_to_stdout: false,
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self {
+            buffer: Vec::new(),
+            echo_to_stdout: true,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.buffer).into_owned()
+    }
+}
+
+impl Default for CaptureLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialLink for CaptureLink {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        self.buffer.push(outgoing);
+
+        if self.echo_to_stdout {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(&[outgoing]);
+            let _ = stdout.flush();
+        }
+
+        0xFF
+    }
+
+    fn captured(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+// SB/SC (https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html)
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    link: Box<dyn SerialLink>,
+
+    // Byte coming back from the link, shifted into SB one bit at a time
+    incoming: u8,
+    bits_shifted: u8,
+    t_cycles: u32,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            sb: 0x00,
+            sc: 0x00,
+            link: Box::new(DisconnectedLink),
+            incoming: 0xFF,
+            bits_shifted: 0,
+            t_cycles: 0,
+        }
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = link;
+    }
+
+    pub fn link(&self) -> &dyn SerialLink {
+        self.link.as_ref()
+    }
+
+    // Only internal clock transfers go anywhere, with an external clock we'd be waiting on
+    // the other gameboy and there never is one
+    fn transferring(&self) -> bool {
+        self.sc & SC_BITS == SC_BITS
+    }
+
+    pub fn step(&mut self, m_cycles: u32, interrupts: &mut Interrupts) {
+        if !self.transferring() {
+            return;
+        }
+
+        self.t_cycles += m_cycles * 4;
+        while self.t_cycles >= T_CYCLES_PER_BIT && self.transferring() {
+            self.t_cycles -= T_CYCLES_PER_BIT;
+            self.shift_bit(interrupts);
+        }
+    }
+
+    // MSB goes out first, the incoming bit comes in at the bottom
+    fn shift_bit(&mut self, interrupts: &mut Interrupts) {
+        let incoming_bit = (self.incoming >> (7 - self.bits_shifted)) & 0x01;
+        self.sb = (self.sb << 1) | incoming_bit;
+        self.bits_shifted += 1;
+
+        if self.bits_shifted == 8 {
+            self.sc &= !SC_TRANSFER_ENABLE;
+            interrupts.request(Interrupt::Serial);
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            SB_ADDRESS => self.sb,
+            SC_ADDRESS => self.sc | !SC_BITS,
+            _ => panic!("Unexpected Serial::read_byte {:#06X}", address),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            SB_ADDRESS => self.sb = value,
+            SC_ADDRESS => {
+                self.sc = value & SC_BITS;
+
+                if self.transferring() {
+                    // The whole byte is exchanged up front, SB still fills in bit by bit
+                    self.incoming = self.link.transfer(self.sb);
+                    self.bits_shifted = 0;
+                    self.t_cycles = 0;
+                }
+            }
+            _ => panic!("Unexpected Serial::write_byte {:#06X} {}", address, value),
+        }
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnected_shifts_in_ff() {
+        let mut serial = Serial::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+
+        serial.write_byte(SB_ADDRESS, 0x00);
+        serial.write_byte(SC_ADDRESS, 0x81);
+        assert_eq!(serial.read_byte(SC_ADDRESS), 0xFF);
+
+        // 4 bits in
+        serial.step(512, &mut interrupts);
+        assert_eq!(serial.read_byte(SB_ADDRESS), 0x0F);
+        assert_eq!(interrupts.pending(), 0);
+
+        serial.step(512, &mut interrupts);
+        assert_eq!(serial.read_byte(SB_ADDRESS), 0xFF);
+        assert_eq!(serial.read_byte(SC_ADDRESS), 0x7F);
+        assert_eq!(interrupts.pending(), Interrupt::Serial.mask());
+    }
+
+    #[test]
+    fn capture_collects_sent_bytes() {
+        let mut serial = Serial::new();
+        let mut interrupts = Interrupts::new();
+        serial.set_link(Box::new(CaptureLink::new()));
+
+        for byte in b"Passed" {
+            serial.write_byte(SB_ADDRESS, *byte);
+            serial.write_byte(SC_ADDRESS, 0x81);
+            serial.step(1024, &mut interrupts);
+        }
+
+        assert_eq!(serial.link().captured(), b"Passed");
+    }
+
+    #[test]
+    fn external_clock_never_completes() {
+        let mut serial = Serial::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+
+        serial.write_byte(SB_ADDRESS, 0x42);
+        serial.write_byte(SC_ADDRESS, 0x80);
+        serial.step(4096, &mut interrupts);
+
+        assert_eq!(serial.read_byte(SB_ADDRESS), 0x42);
+        assert_eq!(serial.read_byte(SC_ADDRESS), 0xFE);
+        assert_eq!(interrupts.pending(), 0);
+    }
+}
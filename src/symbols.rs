@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+// Labels from an RGBDS .sym file, one `bank:address name` per line, ; starts a comment.
+// See https://rgbds.gbdev.io/sym/
+#[derive(Debug)]
+pub struct Symbols {
+    labels: HashMap<(u16, u16), String>,
+    // First label seen at each address, for addresses that aren't banked rom
+    any_bank: HashMap<u16, String>,
+    // The bank and address of each label
+    locations: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self {
+            labels: HashMap::new(),
+            any_bank: HashMap::new(),
+            locations: HashMap::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || format!("line {}: expected bank:address label", index + 1);
+            let (location, name) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let (bank, address) = location.split_once(':').ok_or_else(invalid)?;
+            let bank = u16::from_str_radix(bank, 16).map_err(|_| invalid())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| invalid())?;
+
+            symbols.insert(bank, address, name.trim());
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn load_file(file_path: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(file_path)?)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    // The first label at a location wins
+    pub fn insert(&mut self, bank: u16, address: u16, name: &str) {
+        self.labels
+            .entry((bank, address))
+            .or_insert_with(|| name.to_string());
+        self.any_bank
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+        self.locations
+            .entry(name.to_string())
+            .or_insert((bank, address));
+    }
+
+    // None for the bank matches a label in any bank
+    pub fn label(&self, bank: Option<u16>, address: u16) -> Option<&str> {
+        match bank {
+            Some(bank) => self.labels.get(&(bank, address)),
+            None => self.any_bank.get(&address),
+        }
+        .map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.location(name).map(|(_, address)| address)
+    }
+
+    pub fn location(&self, name: &str) -> Option<(u16, u16)> {
+        self.locations.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "; File generated by rgblink
+00:0150 Start
+00:0150 EntryPoint
+00:0160 Start.loop
+01:4000 BankedOne
+02:4000 BankedTwo
+00:c000 wCounter ; comment
+";
+
+    #[test]
+    fn parses_rgbds_sym() {
+        let symbols = Symbols::parse(SYM).unwrap();
+
+        assert_eq!(symbols.label(Some(0), 0x0150), Some("Start"));
+        assert_eq!(symbols.label(Some(0), 0x0160), Some("Start.loop"));
+        assert_eq!(symbols.label(Some(0), 0xC000), Some("wCounter"));
+        assert_eq!(symbols.label(Some(1), 0x0150), None);
+        assert_eq!(symbols.address("EntryPoint"), Some(0x0150));
+        assert_eq!(symbols.address("Nowhere"), None);
+    }
+
+    #[test]
+    fn banks_are_kept_apart() {
+        let symbols = Symbols::parse(SYM).unwrap();
+
+        assert_eq!(symbols.label(Some(1), 0x4000), Some("BankedOne"));
+        assert_eq!(symbols.label(Some(2), 0x4000), Some("BankedTwo"));
+        assert_eq!(symbols.label(Some(3), 0x4000), None);
+        assert_eq!(symbols.label(None, 0x4000), Some("BankedOne"));
+        assert_eq!(symbols.location("BankedTwo"), Some((2, 0x4000)));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(
+            Symbols::parse("00:0150 Start\n0150 Start\n").unwrap_err(),
+            "line 2: expected bank:address label"
+        );
+        assert!(Symbols::parse("zz:0150 Start").is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::interrupts::{Interrupt, Interrupts};
+
+pub const DIV_ADDRESS: u16 = 0xFF04;
+pub const TIMA_ADDRESS: u16 = 0xFF05;
+pub const TMA_ADDRESS: u16 = 0xFF06;
+pub const TAC_ADDRESS: u16 = 0xFF07;
+
+const TAC_ENABLE: u8 = 0b0000_0100;
+const TAC_BITS: u8 = 0b0000_0111;
+
+// DIV, TIMA, TMA and TAC (https://gbdev.io/pandocs/Timer_and_Divider_Registers.html)
+// TIMA doesn't have its own clock, it counts falling edges of one bit of the 16-bit divider
+// (ANDed with the TAC enable bit). That's why resetting DIV or changing TAC can bump TIMA.
+pub struct Timer {
+    // DIV is just the upper byte of this
+    div_counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+
+    // TIMA reads 0x00 for one m-cycle after overflowing before TMA gets loaded
+    overflow_pending: bool,
+    // The m-cycle TMA was loaded on, writes to TIMA are ignored and writes to TMA go straight through
+    reloading: bool,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            div_counter: 0x0000,
+            tima: 0x00,
+            tma: 0x00,
+            tac: 0x00,
+            overflow_pending: false,
+            reloading: false,
+        }
+    }
+
+    // For starting from a known state, e.g. where the boot rom would have left it
+    pub fn set_div_counter(&mut self, value: u16) {
+        self.div_counter = value;
+    }
+
+    pub fn step(&mut self, m_cycles: u32, interrupts: &mut Interrupts) {
+        for _ in 0..m_cycles {
+            self.tick(interrupts);
+        }
+    }
+
+    fn tick(&mut self, interrupts: &mut Interrupts) {
+        self.reloading = false;
+
+        if self.overflow_pending {
+            self.overflow_pending = false;
+            self.tima = self.tma;
+            self.reloading = true;
+            interrupts.request(Interrupt::Timer);
+        }
+
+        let was_high = self.timer_bit();
+        self.div_counter = self.div_counter.wrapping_add(4);
+        if was_high && !self.timer_bit() {
+            self.increment_tima();
+        }
+    }
+
+    // The divider bit TIMA is watching for the selected frequency, gated by the enable bit
+    fn timer_bit(&self) -> bool {
+        if self.tac & TAC_ENABLE == 0 {
+            return false;
+        }
+
+        let bit = match self.tac & 0b11 {
+            0b00 => 9, // 4096 Hz
+            0b01 => 3, // 262144 Hz
+            0b10 => 5, // 65536 Hz
+            _ => 7,    // 16384 Hz
+        };
+
+        self.div_counter & (1 << bit) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        if self.tima == 0xFF {
+            self.tima = 0x00;
+            self.overflow_pending = true;
+        } else {
+            self.tima += 1;
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            DIV_ADDRESS => (self.div_counter >> 8) as u8,
+            TIMA_ADDRESS => self.tima,
+            TMA_ADDRESS => self.tma,
+            TAC_ADDRESS => self.tac | !TAC_BITS,
+            _ => panic!("Unexpected Timer::read_byte {:#06X}", address),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            DIV_ADDRESS => {
+                // Any write resets the whole counter, which is a falling edge if the bit was set
+                let was_high = self.timer_bit();
+                self.div_counter = 0x0000;
+                if was_high {
+                    self.increment_tima();
+                }
+            }
+            TIMA_ADDRESS => {
+                if !self.reloading {
+                    // Writing during the delay cancels the reload and the interrupt
+                    self.tima = value;
+                    self.overflow_pending = false;
+                }
+            }
+            TMA_ADDRESS => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            TAC_ADDRESS => {
+                let was_high = self.timer_bit();
+                self.tac = value & TAC_BITS;
+                if was_high && !self.timer_bit() {
+                    self.increment_tima();
+                }
+            }
+            _ => panic!("Unexpected Timer::write_byte {:#06X} {}", address, value),
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn div_counts_every_64_m_cycles() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        timer.step(63, &mut interrupts);
+        assert_eq!(timer.read_byte(DIV_ADDRESS), 0x00);
+        timer.step(1, &mut interrupts);
+        assert_eq!(timer.read_byte(DIV_ADDRESS), 0x01);
+
+        timer.write_byte(DIV_ADDRESS, 0xAB);
+        assert_eq!(timer.read_byte(DIV_ADDRESS), 0x00);
+    }
+
+    #[test]
+    fn tima_follows_tac_frequency() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        // Disabled
+        timer.step(1024, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x00);
+
+        // 262144 Hz, every 4 m-cycles
+        timer.write_byte(DIV_ADDRESS, 0x00);
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.step(16, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 4);
+
+        // 4096 Hz, every 256 m-cycles
+        timer.write_byte(DIV_ADDRESS, 0x00);
+        timer.write_byte(TIMA_ADDRESS, 0x00);
+        timer.write_byte(TAC_ADDRESS, 0b100);
+        timer.step(255, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0);
+        timer.step(1, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 1);
+        assert_eq!(timer.read_byte(TAC_ADDRESS), 0b1111_1100);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_late() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+
+        timer.write_byte(TMA_ADDRESS, 0x42);
+        timer.write_byte(TIMA_ADDRESS, 0xFF);
+        timer.write_byte(TAC_ADDRESS, 0b101);
+
+        timer.step(4, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x00);
+        assert_eq!(interrupts.pending(), 0);
+
+        timer.step(1, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x42);
+        assert_eq!(interrupts.pending(), Interrupt::Timer.mask());
+    }
+
+    #[test]
+    fn tima_write_during_delay_cancels_reload() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+        interrupts.write_enabled(0xFF);
+
+        timer.write_byte(TMA_ADDRESS, 0x42);
+        timer.write_byte(TIMA_ADDRESS, 0xFF);
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.step(4, &mut interrupts);
+
+        timer.write_byte(TIMA_ADDRESS, 0x10);
+        timer.step(1, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x10);
+        assert_eq!(interrupts.pending(), 0);
+    }
+
+    #[test]
+    fn writes_on_reload_cycle() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        timer.write_byte(TMA_ADDRESS, 0x42);
+        timer.write_byte(TIMA_ADDRESS, 0xFF);
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.step(5, &mut interrupts);
+
+        // TIMA write loses to the reload, TMA write is copied straight in
+        timer.write_byte(TIMA_ADDRESS, 0x10);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x42);
+        timer.write_byte(TMA_ADDRESS, 0x99);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x99);
+    }
+
+    #[test]
+    fn div_reset_falling_edge_increments_tima() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        // 262144 Hz watches bit 3, set after 2 m-cycles
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.step(2, &mut interrupts);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x00);
+
+        timer.write_byte(DIV_ADDRESS, 0x00);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x01);
+
+        // Bit clear, no glitch
+        timer.write_byte(DIV_ADDRESS, 0x00);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x01);
+    }
+
+    #[test]
+    fn tac_change_falling_edge_increments_tima() {
+        let mut timer = Timer::new();
+        let mut interrupts = Interrupts::new();
+
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.step(2, &mut interrupts);
+
+        // Disabling while the watched bit is high
+        timer.write_byte(TAC_ADDRESS, 0b001);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x01);
+
+        // Switching to a frequency whose bit is low
+        timer.write_byte(TAC_ADDRESS, 0b101);
+        timer.write_byte(TAC_ADDRESS, 0b100);
+        assert_eq!(timer.read_byte(TIMA_ADDRESS), 0x02);
+    }
+}
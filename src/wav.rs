@@ -0,0 +1,140 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_FRAME: u32 = CHANNELS as u32 * BITS_PER_SAMPLE as u32 / 8;
+const HEADER_LENGTH: u32 = 44;
+// Where the two chunk sizes go once we know how much data there is
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+// The RIFF size covers the data plus the rest of the header and has to fit in a u32
+const MAX_DATA_LENGTH: u32 = u32::MAX - (HEADER_LENGTH - 8);
+
+// 16-bit PCM stereo (http://soundfile.sapp.org/doc/WaveFormat/)
+// The header goes out up front with empty sizes and gets patched in finish.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_length: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * BYTES_PER_FRAME).to_le_bytes())?;
+        writer.write_all(&(BYTES_PER_FRAME as u16).to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            data_length: 0,
+        })
+    }
+
+    // Interleaved left/right in -1.0..=1.0, anything outside gets clipped.
+    // Samples that would take the file past the 4 GiB limit are refused whole.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let data_length = u32::try_from(samples.len() * 2)
+            .ok()
+            .and_then(|length| self.data_length.checked_add(length))
+            .filter(|&length| length <= MAX_DATA_LENGTH)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "recording would pass the 4 GiB wav size limit",
+                )
+            })?;
+
+        for sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.writer.write_all(&pcm.to_le_bytes())?;
+        }
+        self.data_length = data_length;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer
+            .write_all(&(HEADER_LENGTH - 8 + self.data_length).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_length.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_header_and_sizes() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.write_samples(&[0.0, 0.0, 0.5, -0.5]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(&bytes, 20), 1);
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 24), 44_100);
+        assert_eq!(u32_at(&bytes, 28), 44_100 * 4);
+        assert_eq!(u16_at(&bytes, 32), 4);
+        assert_eq!(u16_at(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 8);
+    }
+
+    #[test]
+    fn converts_and_clips_samples() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        wav.write_samples(&[1.0, -1.0, 0.5, 2.0, -3.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        let pcm: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(pcm, [32767, -32767, 16384, 32767, -32767]);
+    }
+
+    #[test]
+    fn refuses_samples_past_the_size_limit() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.data_length = MAX_DATA_LENGTH - 4;
+
+        let error = wav.write_samples(&[0.0, 0.0, 0.0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        wav.write_samples(&[0.0, 0.0]).unwrap();
+        assert_eq!(wav.data_length, MAX_DATA_LENGTH);
+
+        let bytes = wav.finish().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(u32_at(&bytes, 4), u32::MAX);
+    }
+}
@@ -0,0 +1,92 @@
+// Runs blargg's test roms headlessly and checks what they print over the serial port.
+// The roms aren't checked in, see tests/fixtures/blargg/README.md. The tests are ignored by
+// default and fail when their rom is missing, run them with `cargo test --test blargg -- --ignored`.
+
+use std::path::Path;
+
+use emoboy::motherboard::{M_CYCLES_PER_FRAME, Motherboard};
+use emoboy::serial::CaptureLink;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/blargg");
+
+// ~59.7 frames a second
+const FRAMES_PER_SECOND: u64 = 60;
+
+enum Outcome {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+// Runs until the rom reports Passed or Failed, or until timeout_seconds of emulated time
+fn run_rom(rom: &str, timeout_seconds: u64) {
+    let path = Path::new(FIXTURES).join(rom);
+    assert!(path.exists(), "{} is missing, put it in {}", rom, FIXTURES);
+
+    let mut motherboard = Motherboard::new();
+    motherboard
+        .load_rom_file(path.to_str().unwrap())
+        .unwrap_or_else(|error| panic!("Couldn't load {}: {}", rom, error))
+        .unwrap();
+    motherboard.set_serial_link(Box::new(CaptureLink::new()));
+
+    let end =
+        motherboard.clock.m_cycles() + timeout_seconds * FRAMES_PER_SECOND * M_CYCLES_PER_FRAME;
+    let outcome = loop {
+        motherboard.run_frame();
+
+        let output = String::from_utf8_lossy(motherboard.serial_output());
+        if output.contains("Failed") {
+            break Outcome::Failed;
+        }
+        if output.contains("Passed") {
+            break Outcome::Passed;
+        }
+        if motherboard.clock.m_cycles() >= end {
+            break Outcome::TimedOut;
+        }
+    };
+
+    let output = String::from_utf8_lossy(motherboard.serial_output());
+    match outcome {
+        Outcome::Passed => {}
+        Outcome::Failed => panic!("{} failed:\n{}", rom, output),
+        Outcome::TimedOut => panic!(
+            "{} didn't finish in {} seconds, output so far:\n{}",
+            rom, timeout_seconds, output
+        ),
+    }
+}
+
+// One test per rom, so every sub-test passes or fails on its own
+macro_rules! blargg_tests {
+    ($($name:ident: $rom:expr, $timeout_seconds:expr;)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the blargg roms in tests/fixtures/blargg"]
+            fn $name() {
+                run_rom($rom, $timeout_seconds);
+            }
+        )*
+    };
+}
+
+blargg_tests! {
+    cpu_instrs_01_special: "cpu_instrs/individual/01-special.gb", 30;
+    cpu_instrs_02_interrupts: "cpu_instrs/individual/02-interrupts.gb", 30;
+    cpu_instrs_03_op_sp_hl: "cpu_instrs/individual/03-op sp,hl.gb", 30;
+    cpu_instrs_04_op_r_imm: "cpu_instrs/individual/04-op r,imm.gb", 30;
+    cpu_instrs_05_op_rp: "cpu_instrs/individual/05-op rp.gb", 30;
+    cpu_instrs_06_ld_r_r: "cpu_instrs/individual/06-ld r,r.gb", 30;
+    cpu_instrs_07_jr_jp_call_ret_rst: "cpu_instrs/individual/07-jr,jp,call,ret,rst.gb", 30;
+    cpu_instrs_08_misc_instrs: "cpu_instrs/individual/08-misc instrs.gb", 30;
+    cpu_instrs_09_op_r_r: "cpu_instrs/individual/09-op r,r.gb", 30;
+    cpu_instrs_10_bit_ops: "cpu_instrs/individual/10-bit ops.gb", 30;
+    cpu_instrs_11_op_a_hl: "cpu_instrs/individual/11-op a,(hl).gb", 30;
+
+    instr_timing: "instr_timing/instr_timing.gb", 10;
+
+    mem_timing_01_read_timing: "mem_timing/individual/01-read_timing.gb", 10;
+    mem_timing_02_write_timing: "mem_timing/individual/02-write_timing.gb", 10;
+    mem_timing_03_modify_timing: "mem_timing/individual/03-modify_timing.gb", 10;
+}